@@ -10,12 +10,28 @@
 //! `3`: There was an error opening the Unix socket stream.
 //! 
 //! `4`: There was an error signaling the daemon.
+//! 
+//! `5`: There was an error receiving the plan of a dry run.
+//! 
+//! `9`: An argument was not recognized.
 
-use std::{env, process, os::unix::net::UnixStream, path::Path, io::Write};
+use std::{env, process, os::unix::net::UnixStream, path::Path, io::{Read, Write}, net::Shutdown};
 
 fn main() {
     println!("Steam Shortcut Sync Client v.{}", option_env!("CARGO_PKG_VERSION").unwrap_or("UNKNOWN"));
 
+    // Print what a synchronization would change instead of running one
+    let mut dry_run = false;
+    for arg in env::args().skip(1) {
+        match arg.as_str() {
+            "--dry-run" => dry_run = true,
+            _ => {
+                eprintln!("Unknown argument {}", arg);
+                process::exit(9);
+            }
+        }
+    }
+
     // Open a socket to communicate with daemon
     let key = match env::var("XDG_RUNTIME_DIR") {
         Ok(val) => val,
@@ -40,11 +56,26 @@ fn main() {
         }
     };
 
-    match stream.write_all(b"RUN_SYNC") {
+    let command: &[u8] = if dry_run { b"DRY_RUN" } else { b"RUN_SYNC" };
+
+    // The daemon reads until the stream is closed, so the write half is shut down once the command is sent
+    match stream.write_all(command).and_then(|_| stream.shutdown(Shutdown::Write)) {
         Err(e) => {
             eprintln!("Failed to signal daemon: {}", e);
             process::exit(4);
         }
+        Ok(()) if dry_run => {},
         Ok(()) => println!("Synchronization requested")
     }
+
+    if dry_run {
+        let mut plan = String::new();
+        match stream.read_to_string(&mut plan) {
+            Ok(_) => print!("{}", plan),
+            Err(e) => {
+                eprintln!("Failed to receive plan: {}", e);
+                process::exit(5);
+            }
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,570 @@
+//! Reads app metadata from Steam's local `appcache/appinfo.vdf` cache.
+//!
+//! The cache is binary KeyValues wrapped in a small per-app header. Versions 27, 28 and 29 of the format are supported.
+
+use std::{collections::{HashMap, HashSet}, fmt, fs, io, path::Path};
+
+use crate::binary_vdf::{BinaryObject, BinaryValue, BinaryVdfParseError, Reader};
+
+const MAGIC_V27: u32 = 0x07564427;
+const MAGIC_V28: u32 = 0x07564428;
+const MAGIC_V29: u32 = 0x07564429;
+
+/// The kind of app, from `common.type`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum AppType {
+    /// A game.
+    Game,
+    /// Software that isn't a game.
+    Application,
+    /// Runtimes, compatibility tools and dedicated servers.
+    Tool,
+    /// A game demo.
+    Demo,
+    /// Downloadable content for another app.
+    Dlc,
+    /// A soundtrack.
+    Music,
+    /// A video.
+    Video,
+    /// Shared configuration, such as Steamworks Common Redistributables.
+    Config,
+    /// Any type not listed above, lowercased.
+    Other(String),
+}
+
+impl AppType {
+    /// Parses a `common.type` value, which Steam writes in inconsistent case.
+    pub fn parse(value: &str) -> AppType {
+        match value.to_ascii_lowercase().as_str() {
+            "game" => AppType::Game,
+            "application" => AppType::Application,
+            "tool" => AppType::Tool,
+            "demo" => AppType::Demo,
+            "dlc" => AppType::Dlc,
+            "music" => AppType::Music,
+            "video" => AppType::Video,
+            "config" => AppType::Config,
+            other => AppType::Other(other.to_string()),
+        }
+    }
+
+    /// Returns `true` for apps a user would launch by themselves, as opposed to runtimes, compatibility tools and add-ons.
+    pub fn is_launchable(&self) -> bool {
+        matches!(self, AppType::Game | AppType::Application | AppType::Demo)
+    }
+}
+
+impl fmt::Display for AppType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AppType::Game => "game",
+            AppType::Application => "application",
+            AppType::Tool => "tool",
+            AppType::Demo => "demo",
+            AppType::Dlc => "dlc",
+            AppType::Music => "music",
+            AppType::Video => "video",
+            AppType::Config => "config",
+            AppType::Other(other) => other,
+        };
+
+        f.write_str(name)
+    }
+}
+
+/// Metadata about one app from `appinfo.vdf`.
+#[derive(Debug, Clone)]
+pub struct AppInfo {
+    /// The Steam app id.
+    pub appid: u32,
+    /// The kind of app.
+    pub app_type: AppType,
+    /// The English name.
+    pub name: String,
+    /// Names by Steam language, such as `german` or `schinese`.
+    pub localized_names: HashMap<String, String>,
+    /// The developers, comma separated.
+    pub developer: Option<String>,
+    /// The publishers, comma separated.
+    pub publisher: Option<String>,
+    /// Genre names, such as `Action` or `Utilities`.
+    pub genres: Vec<String>,
+    /// The release date as a Unix timestamp.
+    pub release_date: Option<u64>,
+    /// Store tag ids, most relevant first.
+    pub store_tags: Vec<u32>,
+    /// The full KeyValues data, for anything not extracted above.
+    pub data: BinaryObject,
+}
+
+impl AppInfo {
+    fn from_data(appid: u32, data: BinaryObject) -> AppInfo {
+        let root = data.get_object("appinfo").unwrap_or(&data);
+        let empty = BinaryObject::default();
+        let common = root.get_object("common").unwrap_or(&empty);
+        let extended = root.get_object("extended").unwrap_or(&empty);
+
+        let localized_names = common.get_object("name_localized")
+            .map(|names| names.iter()
+                .filter_map(|(lang, name)| Some((lang.to_string(), name.as_str()?.to_string())))
+                .collect())
+            .unwrap_or_default();
+
+        // Newer entries list companies under `associations`, older ones only in `extended`
+        let association = |kind: &str| {
+            let names: Vec<&str> = common.get_object("associations")
+                .map(|a| a.iter()
+                    .filter_map(|(_, v)| v.as_object())
+                    .filter(|a| a.get_str("type") == Some(kind))
+                    .filter_map(|a| a.get_str("name"))
+                    .collect())
+                .unwrap_or_default();
+
+            if names.is_empty() {
+                extended.get_str(kind).map(String::from)
+            } else {
+                Some(names.join(", "))
+            }
+        };
+
+        let genres = list(common.get_object("genres"))
+            .filter_map(genre_name)
+            .map(String::from)
+            .collect();
+
+        let release_date = ["steam_release_date", "original_release_date"].iter()
+            .filter_map(|k| common.get(k).and_then(number))
+            .find(|&d| d > 0);
+
+        AppInfo {
+            appid,
+            app_type: AppType::parse(common.get_str("type").unwrap_or_default()),
+            name: common.get_str("name").unwrap_or_default().to_string(),
+            localized_names,
+            developer: association("developer"),
+            publisher: association("publisher"),
+            genres,
+            release_date,
+            store_tags: list(common.get_object("store_tags")).map(|t| t as u32).collect(),
+            data,
+        }
+    }
+
+    /// Returns the app's name for each freedesktop locale that has a translation.
+    pub fn names_by_locale(&self) -> Vec<(&'static str, &str)> {
+        let mut names: Vec<(&'static str, &str)> = self.localized_names.iter()
+            .filter_map(|(lang, name)| Some((steam_language_locale(lang)?, name.as_str())))
+            .filter(|(_, name)| !name.is_empty())
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    /// Returns the names of the app's store tags that are known offline.
+    pub fn store_tag_names(&self) -> Vec<&'static str> {
+        self.store_tags.iter().filter_map(|&t| store_tag_name(t)).collect()
+    }
+}
+
+/// Possible errors when reading `appinfo.vdf`.
+#[derive(Debug)]
+pub struct AppInfoError {
+    /// The specific kind of error.
+    pub kind: AppInfoErrorKind
+}
+
+#[derive(Debug)]
+pub enum AppInfoErrorKind {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file uses a format version that isn't supported.
+    UnsupportedVersion(u32),
+    /// The file was truncated or malformed.
+    Parse(BinaryVdfParseError),
+}
+
+impl From<BinaryVdfParseError> for AppInfoError {
+    fn from(e: BinaryVdfParseError) -> Self {
+        AppInfoError { kind: AppInfoErrorKind::Parse(e) }
+    }
+}
+
+/// Reads the metadata of the Steam installation at `install_dir`.
+///
+/// If `appids` is given, only those apps are decoded, which is much faster than decoding the whole cache.
+pub fn load_appinfo(install_dir: &Path, appids: Option<&HashSet<u32>>) -> Result<HashMap<u32, AppInfo>, AppInfoError> {
+    let data = fs::read(install_dir.join("appcache/appinfo.vdf")).map_err(|e| AppInfoError { kind: AppInfoErrorKind::Io(e) })?;
+
+    parse_appinfo(&data, appids)
+}
+
+/// Parses the contents of an `appinfo.vdf` file.
+///
+/// If `appids` is given, only those apps are decoded.
+/// Malformed entries are logged and skipped, and a truncated file keeps the apps read before the damage.
+pub fn parse_appinfo(data: &[u8], appids: Option<&HashSet<u32>>) -> Result<HashMap<u32, AppInfo>, AppInfoError> {
+    let mut reader = Reader::new(data);
+    let magic = reader.u32()?;
+    let _universe = reader.u32()?;
+
+    let keys = match magic {
+        MAGIC_V27 | MAGIC_V28 => Vec::new(),
+        MAGIC_V29 => {
+            let offset = reader.u64()? as usize;
+            read_string_table(data, offset)?
+        },
+        other => return Err(AppInfoError { kind: AppInfoErrorKind::UnsupportedVersion(other) })
+    };
+
+    // Fixed fields after `size`: info state, last updated, PICS token, text SHA-1, change number and, since v28, binary SHA-1
+    let skip = 4 + 4 + 8 + 20 + 4 + if magic == MAGIC_V27 { 0 } else { 20 };
+
+    let mut apps = HashMap::new();
+    loop {
+        let appid = match reader.u32() {
+            Ok(0) => break,
+            Ok(appid) => appid,
+            Err(e) => {
+                eprintln!("appinfo.vdf ends without its final entry: {}", e);
+                break;
+            }
+        };
+
+        // Without a trustworthy size there is no telling where the next entry starts
+        let entry = match reader.u32().and_then(|size| reader.bytes(size as usize)) {
+            Ok(entry) => entry,
+            Err(e) => {
+                eprintln!("Skipping the rest of appinfo.vdf, app {} is truncated: {}", appid, e);
+                break;
+            }
+        };
+
+        if appids.map(|ids| !ids.contains(&appid)).unwrap_or(false) || entry.len() < skip {
+            continue;
+        }
+
+        let mut entry_reader = if magic == MAGIC_V29 {
+            Reader::with_key_table(&entry[skip..], &keys)
+        } else {
+            Reader::new(&entry[skip..])
+        };
+
+        match entry_reader.object() {
+            Ok(data) => { apps.insert(appid, AppInfo::from_data(appid, data)); },
+            Err(e) => eprintln!("Skipping malformed app {} in appinfo.vdf: {}", appid, e)
+        }
+    }
+
+    Ok(apps)
+}
+
+fn read_string_table(data: &[u8], offset: usize) -> Result<Vec<String>, AppInfoError> {
+    let mut reader = Reader::new(data);
+    reader.bytes(offset)?;
+
+    let count = reader.u32()?;
+    (0..count).map(|_| reader.string().map_err(AppInfoError::from)).collect()
+}
+
+// Numbers are stored as integers or as decimal strings depending on the key and the age of the entry
+fn number(value: &BinaryValue) -> Option<u64> {
+    match value.as_i64() {
+        Some(i) => u64::try_from(i).ok(),
+        None => value.as_str()?.trim().parse().ok()
+    }
+}
+
+// Lists are blocks keyed `0`, `1`, ... with numeric values
+fn list(object: Option<&BinaryObject>) -> impl Iterator<Item = u64> + '_ {
+    object.into_iter().flat_map(|o| o.iter().filter_map(|(_, v)| number(v)))
+}
+
+/// Converts a Steam language name into the locale used for localized desktop entry keys.
+pub fn steam_language_locale(language: &str) -> Option<&'static str> {
+    Some(match language {
+        "arabic" => "ar",
+        "brazilian" => "pt_BR",
+        "bulgarian" => "bg",
+        "czech" => "cs",
+        "danish" => "da",
+        "dutch" => "nl",
+        "english" => "en",
+        "finnish" => "fi",
+        "french" => "fr",
+        "german" => "de",
+        "greek" => "el",
+        "hungarian" => "hu",
+        "indonesian" => "id",
+        "italian" => "it",
+        "japanese" => "ja",
+        "koreana" => "ko",
+        "latam" => "es_419",
+        "norwegian" => "nb",
+        "polish" => "pl",
+        "portuguese" => "pt",
+        "romanian" => "ro",
+        "russian" => "ru",
+        "schinese" => "zh_CN",
+        "spanish" => "es",
+        "swedish" => "sv",
+        "tchinese" => "zh_TW",
+        "thai" => "th",
+        "turkish" => "tr",
+        "ukrainian" => "uk",
+        "vietnamese" => "vi",
+        _ => return None
+    })
+}
+
+/// Returns the English name of a Steam genre id.
+pub fn genre_name(id: u64) -> Option<&'static str> {
+    Some(match id {
+        1 => "Action",
+        2 => "Strategy",
+        3 => "RPG",
+        4 => "Casual",
+        9 => "Racing",
+        18 => "Sports",
+        23 => "Indie",
+        25 => "Adventure",
+        28 => "Simulation",
+        29 => "Massively Multiplayer",
+        37 => "Free to Play",
+        51 => "Animation & Modeling",
+        52 => "Audio Production",
+        53 => "Design & Illustration",
+        54 => "Education",
+        55 => "Photo Editing",
+        56 => "Software Training",
+        57 => "Utilities",
+        58 => "Video Production",
+        59 => "Web Publishing",
+        60 => "Game Development",
+        70 => "Early Access",
+        _ => return None
+    })
+}
+
+/// Returns the English name of a common Steam store tag id.
+///
+/// Steam doesn't cache tag names locally, so only the most widely used tags are known.
+pub fn store_tag_name(id: u32) -> Option<&'static str> {
+    Some(match id {
+        9 => "Strategy",
+        19 => "Action",
+        21 => "Adventure",
+        84 => "Design & Illustration",
+        87 => "Utilities",
+        113 => "Free to Play",
+        122 => "RPG",
+        128 => "Massively Multiplayer",
+        492 => "Indie",
+        493 => "Early Access",
+        597 => "Casual",
+        599 => "Simulation",
+        699 => "Racing",
+        701 => "Sports",
+        784 => "Video Production",
+        809 => "Photo Editing",
+        1027 => "Audio Production",
+        1036 => "Education",
+        1621 => "Music",
+        1625 => "Platformer",
+        1628 => "Metroidvania",
+        1643 => "Building",
+        1644 => "Driving",
+        1662 => "Survival",
+        1663 => "FPS",
+        1664 => "Puzzle",
+        1666 => "Card Game",
+        1667 => "Horror",
+        1676 => "RTS",
+        1677 => "Turn-Based",
+        1684 => "Fantasy",
+        1685 => "Co-op",
+        1695 => "Open World",
+        1697 => "Third Person",
+        1698 => "Point & Click",
+        1702 => "Crafting",
+        1708 => "Tactical",
+        1716 => "Roguelike",
+        1719 => "Comedy",
+        1741 => "Turn-Based Strategy",
+        1742 => "Story Rich",
+        1743 => "Fighting",
+        1752 => "Rhythm",
+        1773 => "Arcade",
+        1774 => "Shooter",
+        3799 => "Visual Novel",
+        3810 => "Sandbox",
+        3834 => "Exploration",
+        3859 => "Multiplayer",
+        3871 => "2D",
+        3942 => "Sci-fi",
+        3959 => "Roguelite",
+        3964 => "Pixel Graphics",
+        4085 => "Anime",
+        4166 => "Atmospheric",
+        4182 => "Singleplayer",
+        4191 => "3D",
+        _ => return None
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::binary_vdf::{TYPE_END, TYPE_INT32, TYPE_OBJECT, TYPE_STRING};
+
+    use super::*;
+
+    enum Value {
+        Object(Vec<(&'static str, Value)>),
+        String(&'static str),
+        Int32(i32),
+    }
+
+    fn game(name: &'static str, app_type: &'static str) -> Value {
+        Value::Object(vec![("appinfo", Value::Object(vec![("common", Value::Object(vec![
+            ("name", Value::String(name)),
+            ("type", Value::String(app_type)),
+            ("name_localized", Value::Object(vec![("german", Value::String("Spiel")), ("klingon", Value::String("Quj"))])),
+            ("genres", Value::Object(vec![("0", Value::String("1")), ("1", Value::Int32(23))])),
+            ("store_tags", Value::Object(vec![("0", Value::Int32(1662)), ("1", Value::Int32(999_999))])),
+            ("steam_release_date", Value::Int32(1_600_000_000)),
+            ("associations", Value::Object(vec![("0", Value::Object(vec![("type", Value::String("developer")), ("name", Value::String("Studio"))]))])),
+        ]))]))])
+    }
+
+    // v29 stores keys as indices into the string table at the end of the file
+    fn encode(value: &Value, keys: &mut Option<Vec<String>>, out: &mut Vec<u8>) {
+        let pairs = match value {
+            Value::Object(pairs) => pairs,
+            _ => unreachable!()
+        };
+
+        for (key, value) in pairs {
+            out.push(match value {
+                Value::Object(_) => TYPE_OBJECT,
+                Value::String(_) => TYPE_STRING,
+                Value::Int32(_) => TYPE_INT32,
+            });
+            match keys {
+                Some(keys) => {
+                    let index = keys.iter().position(|k| k == key).unwrap_or_else(|| {
+                        keys.push(key.to_string());
+                        keys.len() - 1
+                    });
+                    out.extend((index as u32).to_le_bytes());
+                },
+                None => {
+                    out.extend(key.as_bytes());
+                    out.push(0);
+                }
+            }
+            match value {
+                Value::Object(_) => encode(value, keys, out),
+                Value::String(s) => {
+                    out.extend(s.as_bytes());
+                    out.push(0);
+                },
+                Value::Int32(i) => out.extend(i.to_le_bytes()),
+            }
+        }
+        out.push(TYPE_END);
+    }
+
+    fn appinfo(magic: u32, entries: &[(u32, Value)]) -> Vec<u8> {
+        let mut keys = if magic == MAGIC_V29 { Some(Vec::new()) } else { None };
+        let header_skip = 4 + 4 + 8 + 20 + 4 + if magic == MAGIC_V27 { 0 } else { 20 };
+
+        let mut body = Vec::new();
+        for (appid, value) in entries {
+            let mut entry = vec![0; header_skip];
+            encode(value, &mut keys, &mut entry);
+            body.extend(appid.to_le_bytes());
+            body.extend((entry.len() as u32).to_le_bytes());
+            body.extend(entry);
+        }
+        body.extend(0u32.to_le_bytes());
+
+        let mut out = Vec::new();
+        out.extend(magic.to_le_bytes());
+        out.extend(1u32.to_le_bytes());
+        if let Some(keys) = keys {
+            let offset = out.len() + 8 + body.len();
+            out.extend((offset as u64).to_le_bytes());
+            out.extend(body);
+            out.extend((keys.len() as u32).to_le_bytes());
+            for key in keys {
+                out.extend(key.as_bytes());
+                out.push(0);
+            }
+        } else {
+            out.extend(body);
+        }
+
+        out
+    }
+
+    fn check_versions(check: impl Fn(&HashMap<u32, AppInfo>)) {
+        for magic in [MAGIC_V27, MAGIC_V28, MAGIC_V29] {
+            let data = appinfo(magic, &[(10, game("Counter-Strike", "Game")), (228980, game("Redistributables", "Config"))]);
+            check(&parse_appinfo(&data, None).unwrap());
+        }
+    }
+
+    #[test]
+    fn parses_every_supported_version() {
+        check_versions(|apps| {
+            let game = &apps[&10];
+            assert_eq!(game.name, "Counter-Strike");
+            assert_eq!(game.app_type, AppType::Game);
+            assert_eq!(game.developer.as_deref(), Some("Studio"));
+            assert_eq!(game.genres, ["Action", "Indie"]);
+            assert_eq!(game.release_date, Some(1_600_000_000));
+            assert_eq!(game.store_tag_names(), ["Survival"]);
+            assert_eq!(game.names_by_locale(), [("de", "Spiel")]);
+            assert_eq!(apps[&228980].app_type, AppType::Config);
+            assert!(!apps[&228980].app_type.is_launchable());
+        });
+    }
+
+    #[test]
+    fn decodes_only_requested_apps() {
+        let data = appinfo(MAGIC_V29, &[(10, game("A", "Game")), (20, game("B", "game"))]);
+        let apps = parse_appinfo(&data, Some(&HashSet::from([20]))).unwrap();
+
+        assert_eq!(apps.keys().collect::<Vec<_>>(), [&20]);
+        assert_eq!(apps[&20].app_type, AppType::Game);
+    }
+
+    #[test]
+    fn skips_malformed_entries() {
+        let mut data = appinfo(MAGIC_V28, &[(10, game("A", "Game")), (20, game("B", "Game")), (30, game("C", "Game"))]);
+
+        // Corrupts the first value type of app 20, which sits right after its header
+        let entry_20 = data.windows(4).position(|w| w == 20u32.to_le_bytes()).unwrap();
+        data[entry_20 + 8 + 60] = 0x09;
+
+        let apps = parse_appinfo(&data, None).unwrap();
+        assert_eq!(apps.len(), 2);
+        assert!(apps.contains_key(&10) && apps.contains_key(&30));
+    }
+
+    #[test]
+    fn keeps_apps_read_before_a_truncation() {
+        let data = appinfo(MAGIC_V27, &[(10, game("A", "Game")), (20, game("B", "Game"))]);
+        let apps = parse_appinfo(&data[..data.len() - 20], None).unwrap();
+
+        assert_eq!(apps.keys().collect::<Vec<_>>(), [&10]);
+    }
+
+    #[test]
+    fn rejects_unknown_versions() {
+        let error = parse_appinfo(&appinfo(0x07564426, &[]), None).unwrap_err();
+
+        assert!(matches!(error.kind, AppInfoErrorKind::UnsupportedVersion(0x07564426)));
+    }
+}
@@ -0,0 +1,484 @@
+//! A reader and writer for Valve's binary KeyValues format, used by files such as `userdata/<id>/config/shortcuts.vdf`.
+
+use std::{borrow::Cow, fmt, str};
+
+pub(crate) const TYPE_OBJECT: u8 = 0x00;
+pub(crate) const TYPE_STRING: u8 = 0x01;
+pub(crate) const TYPE_INT32: u8 = 0x02;
+pub(crate) const TYPE_FLOAT32: u8 = 0x03;
+pub(crate) const TYPE_POINTER: u8 = 0x04;
+pub(crate) const TYPE_WIDE_STRING: u8 = 0x05;
+pub(crate) const TYPE_COLOR: u8 = 0x06;
+pub(crate) const TYPE_UINT64: u8 = 0x07;
+pub(crate) const TYPE_END: u8 = 0x08;
+pub(crate) const TYPE_INT64: u8 = 0x0A;
+
+/// A value in a binary KeyValues document.
+#[derive(Debug, Clone, PartialEq)]
+pub enum BinaryValue {
+    /// A nested block of key-value pairs.
+    Object(BinaryObject),
+    /// A string, kept as the bytes that were read since Steam doesn't always write valid UTF-8 for user-provided names.
+    String(Vec<u8>),
+    /// A signed 32-bit integer.
+    Int32(i32),
+    /// A 32-bit float.
+    Float32(f32),
+    /// A 32-bit pointer, which is meaningless outside of the process that wrote it.
+    Pointer(i32),
+    /// A UTF-16 string.
+    WideString(String),
+    /// A 32-bit RGBA color.
+    Color(i32),
+    /// An unsigned 64-bit integer.
+    UInt64(u64),
+    /// A signed 64-bit integer.
+    Int64(i64),
+}
+
+impl BinaryValue {
+    /// Returns the string if this value is one and is valid UTF-8.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            BinaryValue::String(s) => str::from_utf8(s).ok(),
+            BinaryValue::WideString(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Returns the bytes of the string if this value is a narrow string.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            BinaryValue::String(s) => Some(s),
+            _ => None
+        }
+    }
+
+    /// Returns the string if this value is one, replacing invalid UTF-8 for display.
+    pub fn to_string_lossy(&self) -> Option<Cow<'_, str>> {
+        match self {
+            BinaryValue::String(s) => Some(String::from_utf8_lossy(s)),
+            BinaryValue::WideString(s) => Some(Cow::Borrowed(s)),
+            _ => None
+        }
+    }
+
+    /// Returns the block if this value is one.
+    pub fn as_object(&self) -> Option<&BinaryObject> {
+        match self {
+            BinaryValue::Object(o) => Some(o),
+            _ => None
+        }
+    }
+
+    /// Returns the value as an integer if it is any integer type.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            BinaryValue::Int32(i) | BinaryValue::Pointer(i) | BinaryValue::Color(i) => Some(i as i64),
+            BinaryValue::UInt64(i) => Some(i as i64),
+            BinaryValue::Int64(i) => Some(i),
+            _ => None
+        }
+    }
+}
+
+/// An ordered block of key-value pairs.
+///
+/// Keys are kept in file order. Lookups are case-insensitive, like Steam's own.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BinaryObject {
+    entries: Vec<(String, BinaryValue)>,
+}
+
+impl BinaryObject {
+    /// Returns the first value stored under `key`.
+    pub fn get(&self, key: &str) -> Option<&BinaryValue> {
+        self.entries.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Returns the first string stored under `key`.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(BinaryValue::as_str)
+    }
+
+    /// Returns the first string stored under `key`, replacing invalid UTF-8 for display.
+    pub fn get_string_lossy(&self, key: &str) -> Option<Cow<'_, str>> {
+        self.get(key).and_then(BinaryValue::to_string_lossy)
+    }
+
+    /// Returns the first block stored under `key`.
+    pub fn get_object(&self, key: &str) -> Option<&BinaryObject> {
+        self.get(key).and_then(BinaryValue::as_object)
+    }
+
+    /// Returns the first integer stored under `key`.
+    pub fn get_i64(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(BinaryValue::as_i64)
+    }
+
+    /// Iterates over every key-value pair in file order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &BinaryValue)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Returns the first value stored under `key` for modification.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut BinaryValue> {
+        self.entries.iter_mut()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Iterates over every key-value pair in file order, allowing the values to be modified.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&str, &mut BinaryValue)> {
+        self.entries.iter_mut().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Appends a key-value pair.
+    pub fn push(&mut self, key: String, value: BinaryValue) {
+        self.entries.push((key, value));
+    }
+
+    /// Replaces the first value stored under `key`, or appends it if there is none.
+    pub fn set(&mut self, key: &str, value: BinaryValue) {
+        match self.get_mut(key) {
+            Some(v) => *v = value,
+            None => self.push(key.to_string(), value)
+        }
+    }
+
+    /// Removes every value stored under `key`.
+    pub fn remove(&mut self, key: &str) {
+        self.entries.retain(|(k, _)| !k.eq_ignore_ascii_case(key));
+    }
+
+    /// Returns the number of key-value pairs.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the block has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Possible errors when parsing a binary KeyValues document.
+#[derive(Debug, Clone)]
+pub struct BinaryVdfParseError {
+    /// The specific kind of error.
+    pub kind: BinaryVdfParseErrorKind,
+    /// The byte offset the error occured at.
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum BinaryVdfParseErrorKind {
+    /// The document ended inside a value or block.
+    UnexpectedEof,
+    /// A value had a type byte that isn't part of the format.
+    UnknownType(u8),
+    /// A string wasn't valid UTF-16, or a key referred to a missing string table entry.
+    InvalidString,
+}
+
+impl fmt::Display for BinaryVdfParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            BinaryVdfParseErrorKind::UnexpectedEof => write!(f, "unexpected end of file at byte {}", self.offset),
+            BinaryVdfParseErrorKind::UnknownType(t) => write!(f, "unknown value type {:#04x} at byte {}", t, self.offset),
+            BinaryVdfParseErrorKind::InvalidString => write!(f, "invalid string at byte {}", self.offset),
+        }
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    // Newer `appinfo.vdf` files store keys as indices into a shared string table
+    keys: Option<&'a [String]>,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Self {
+        Reader { data, pos: 0, keys: None }
+    }
+
+    pub(crate) fn with_key_table(data: &'a [u8], keys: &'a [String]) -> Self {
+        Reader { data, pos: 0, keys: Some(keys) }
+    }
+
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    pub(crate) fn error(&self, kind: BinaryVdfParseErrorKind) -> BinaryVdfParseError {
+        BinaryVdfParseError { kind, offset: self.pos }
+    }
+
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], BinaryVdfParseError> {
+        if self.data.len() - self.pos < len {
+            return Err(self.error(BinaryVdfParseErrorKind::UnexpectedEof));
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, BinaryVdfParseError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, BinaryVdfParseError> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, BinaryVdfParseError> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    pub(crate) fn string(&mut self) -> Result<String, BinaryVdfParseError> {
+        Ok(String::from_utf8_lossy(self.raw_string()?).into_owned())
+    }
+
+    fn raw_string(&mut self) -> Result<&'a [u8], BinaryVdfParseError> {
+        let start = self.pos;
+        let len = match self.data[start..].iter().position(|&b| b == 0) {
+            Some(len) => len,
+            None => return Err(self.error(BinaryVdfParseErrorKind::UnexpectedEof))
+        };
+
+        self.pos += len + 1;
+        Ok(&self.data[start..start + len])
+    }
+
+    fn key(&mut self) -> Result<String, BinaryVdfParseError> {
+        match self.keys {
+            Some(keys) => {
+                let index = self.u32()? as usize;
+                keys.get(index).cloned().ok_or(self.error(BinaryVdfParseErrorKind::InvalidString))
+            },
+            None => self.string()
+        }
+    }
+
+    fn wide_string(&mut self) -> Result<String, BinaryVdfParseError> {
+        let mut units = Vec::new();
+        loop {
+            let unit = u16::from_le_bytes(self.bytes(2)?.try_into().unwrap());
+            if unit == 0 {
+                break;
+            }
+            units.push(unit);
+        }
+
+        String::from_utf16(&units).map_err(|_| self.error(BinaryVdfParseErrorKind::InvalidString))
+    }
+
+    /// Reads the pairs of a block up to and including its end marker.
+    pub(crate) fn object(&mut self) -> Result<BinaryObject, BinaryVdfParseError> {
+        let mut object = BinaryObject::default();
+
+        loop {
+            let kind = self.u8()?;
+            if kind == TYPE_END {
+                return Ok(object);
+            }
+
+            let key = self.key()?;
+            let value = self.value(kind)?;
+            object.push(key, value);
+        }
+    }
+
+    fn value(&mut self, kind: u8) -> Result<BinaryValue, BinaryVdfParseError> {
+        Ok(match kind {
+            TYPE_OBJECT => BinaryValue::Object(self.object()?),
+            TYPE_STRING => BinaryValue::String(self.raw_string()?.to_vec()),
+            TYPE_INT32 => BinaryValue::Int32(self.u32()? as i32),
+            TYPE_FLOAT32 => BinaryValue::Float32(f32::from_bits(self.u32()?)),
+            TYPE_POINTER => BinaryValue::Pointer(self.u32()? as i32),
+            TYPE_WIDE_STRING => BinaryValue::WideString(self.wide_string()?),
+            TYPE_COLOR => BinaryValue::Color(self.u32()? as i32),
+            TYPE_UINT64 => BinaryValue::UInt64(self.u64()?),
+            TYPE_INT64 => BinaryValue::Int64(self.u64()? as i64),
+            t => return Err(self.error(BinaryVdfParseErrorKind::UnknownType(t)))
+        })
+    }
+}
+
+/// Parses a binary KeyValues document into its top-level block.
+///
+/// A document that ends without a final end marker, as some writers produce, is accepted.
+pub fn parse(data: &[u8]) -> Result<BinaryObject, BinaryVdfParseError> {
+    let mut reader = Reader::new(data);
+    let mut root = BinaryObject::default();
+
+    while reader.position() < data.len() {
+        let kind = reader.u8()?;
+        if kind == TYPE_END {
+            break;
+        }
+
+        let key = reader.key()?;
+        let value = reader.value(kind)?;
+        root.push(key, value);
+    }
+
+    Ok(root)
+}
+
+/// Serializes a top-level block into a binary KeyValues document.
+///
+/// The result ends with a final end marker, which is what Steam writes.
+pub fn write(root: &BinaryObject) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_object(root, &mut out);
+
+    out
+}
+
+fn write_object(object: &BinaryObject, out: &mut Vec<u8>) {
+    for (key, value) in object.iter() {
+        let kind = match value {
+            BinaryValue::Object(_) => TYPE_OBJECT,
+            BinaryValue::String(_) => TYPE_STRING,
+            BinaryValue::Int32(_) => TYPE_INT32,
+            BinaryValue::Float32(_) => TYPE_FLOAT32,
+            BinaryValue::Pointer(_) => TYPE_POINTER,
+            BinaryValue::WideString(_) => TYPE_WIDE_STRING,
+            BinaryValue::Color(_) => TYPE_COLOR,
+            BinaryValue::UInt64(_) => TYPE_UINT64,
+            BinaryValue::Int64(_) => TYPE_INT64,
+        };
+
+        out.push(kind);
+        write_string(key.as_bytes(), out);
+
+        match value {
+            BinaryValue::Object(o) => write_object(o, out),
+            BinaryValue::String(s) => write_string(s, out),
+            BinaryValue::Int32(i) | BinaryValue::Pointer(i) | BinaryValue::Color(i) => out.extend(i.to_le_bytes()),
+            BinaryValue::Float32(f) => out.extend(f.to_le_bytes()),
+            BinaryValue::WideString(s) => {
+                for unit in s.encode_utf16() {
+                    out.extend(unit.to_le_bytes());
+                }
+                out.extend([0, 0]);
+            },
+            BinaryValue::UInt64(i) => out.extend(i.to_le_bytes()),
+            BinaryValue::Int64(i) => out.extend(i.to_le_bytes()),
+        }
+    }
+
+    out.push(TYPE_END);
+}
+
+fn write_string(s: &[u8], out: &mut Vec<u8>) {
+    // Keys and values are NUL-terminated, so an embedded NUL would truncate them anyway
+    out.extend(s.iter().filter(|&&b| b != 0));
+    out.push(0);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shortcut() -> BinaryObject {
+        let mut entry = BinaryObject::default();
+        entry.push("appid".to_string(), BinaryValue::Int32(-1_234_567));
+        entry.push("AppName".to_string(), BinaryValue::String("Some Game".into()));
+        entry.push("Exe".to_string(), BinaryValue::String("\"/usr/bin/game\"".into()));
+        entry.push("IsHidden".to_string(), BinaryValue::Int32(0));
+        entry.push("LastPlayTime".to_string(), BinaryValue::Int32(1_700_000_000));
+        entry.push("tags".to_string(), BinaryValue::Object(BinaryObject::default()));
+
+        let mut shortcuts = BinaryObject::default();
+        shortcuts.push("0".to_string(), BinaryValue::Object(entry));
+
+        let mut root = BinaryObject::default();
+        root.push("shortcuts".to_string(), BinaryValue::Object(shortcuts));
+        root
+    }
+
+    #[test]
+    fn writes_what_steam_writes() {
+        let mut root = BinaryObject::default();
+        let mut inner = BinaryObject::default();
+        inner.push("a".to_string(), BinaryValue::String("b".into()));
+        inner.push("n".to_string(), BinaryValue::Int32(1));
+        root.push("s".to_string(), BinaryValue::Object(inner));
+
+        assert_eq!(write(&root), b"\x00s\x00\x01a\x00b\x00\x02n\x00\x01\x00\x00\x00\x08\x08");
+    }
+
+    #[test]
+    fn round_trips_shortcuts() {
+        let root = shortcut();
+        let parsed = parse(&write(&root)).unwrap();
+
+        assert_eq!(parsed, root);
+        let entry = parsed.get_object("shortcuts").and_then(|s| s.get_object("0")).unwrap();
+        assert_eq!(entry.get_str("AppName"), Some("Some Game"));
+        assert_eq!(entry.get_i64("appid"), Some(-1_234_567));
+    }
+
+    #[test]
+    fn keeps_invalid_utf8_strings_intact() {
+        let data = b"\x01AppName\x00Caf\xe9\x00\x08";
+        let root = parse(data).unwrap();
+
+        assert_eq!(root.get("AppName"), Some(&BinaryValue::String(b"Caf\xe9".to_vec())));
+        assert_eq!(root.get_str("AppName"), None);
+        assert_eq!(root.get_string_lossy("AppName").as_deref(), Some("Caf\u{fffd}"));
+        assert_eq!(write(&root), data);
+    }
+
+    #[test]
+    fn round_trips_every_type() {
+        let mut root = BinaryObject::default();
+        root.push("float".to_string(), BinaryValue::Float32(1.5));
+        root.push("pointer".to_string(), BinaryValue::Pointer(7));
+        root.push("wide".to_string(), BinaryValue::WideString("Wide ÿ".to_string()));
+        root.push("color".to_string(), BinaryValue::Color(-1));
+        root.push("uint64".to_string(), BinaryValue::UInt64(u64::MAX));
+        root.push("int64".to_string(), BinaryValue::Int64(i64::MIN));
+
+        assert_eq!(parse(&write(&root)).unwrap(), root);
+    }
+
+    #[test]
+    fn accepts_a_missing_final_end_marker() {
+        let mut data = write(&shortcut());
+        data.pop();
+
+        assert_eq!(parse(&data).unwrap(), shortcut());
+    }
+
+    #[test]
+    fn rejects_truncated_documents() {
+        let data = write(&shortcut());
+        let error = parse(&data[..data.len() / 2]).unwrap_err();
+
+        assert!(matches!(error.kind, BinaryVdfParseErrorKind::UnexpectedEof));
+    }
+
+    #[test]
+    fn rejects_unknown_types() {
+        let error = parse(b"\x09key\x00").unwrap_err();
+
+        assert!(matches!(error.kind, BinaryVdfParseErrorKind::UnknownType(0x09)));
+    }
+
+    #[test]
+    fn resolves_keys_from_a_string_table() {
+        let keys = vec!["name".to_string(), "common".to_string()];
+        let data = b"\x00\x01\x00\x00\x00\x01\x00\x00\x00\x00Game\x00\x08\x08";
+        let mut reader = Reader::with_key_table(data, &keys);
+        let object = reader.object().unwrap();
+
+        assert_eq!(object.get_object("common").and_then(|c| c.get_str("name")), Some("Game"));
+    }
+}
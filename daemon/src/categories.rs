@@ -0,0 +1,105 @@
+//! Menu categories and search keywords of launchers.
+//!
+//! Steam's genres and store tags are mapped to the categories of the freedesktop menu specification, so games end up in the right submenus.
+
+use std::collections::HashMap;
+
+use crate::appinfo::AppType;
+
+/// The category of each genre and store tag that has one, unless the configuration maps it differently.
+pub const DEFAULT_CATEGORIES: &[(&str, &str)] = &[
+    ("Action", "ActionGame"),
+    ("Fighting", "ActionGame"),
+    ("Adventure", "AdventureGame"),
+    ("Point & Click", "AdventureGame"),
+    ("Visual Novel", "AdventureGame"),
+    ("Arcade", "ArcadeGame"),
+    ("Platformer", "ArcadeGame"),
+    ("Card Game", "CardGame"),
+    ("Puzzle", "LogicGame"),
+    ("RPG", "RolePlaying"),
+    ("FPS", "Shooter"),
+    ("Shooter", "Shooter"),
+    ("Simulation", "Simulation"),
+    ("Racing", "SportsGame"),
+    ("Sports", "SportsGame"),
+    ("Strategy", "StrategyGame"),
+    ("RTS", "StrategyGame"),
+    ("Turn-Based Strategy", "StrategyGame"),
+    ("Animation & Modeling", "Graphics"),
+    ("Design & Illustration", "Graphics"),
+    ("Photo Editing", "Graphics"),
+    ("Audio Production", "AudioVideo"),
+    ("Video Production", "AudioVideo"),
+    ("Education", "Education"),
+    ("Software Training", "Education"),
+    ("Game Development", "Development"),
+    ("Web Publishing", "Network"),
+    ("Utilities", "Utility"),
+];
+
+/// Categories that menus are built from, as opposed to the additional categories that refine them.
+const MAIN_CATEGORIES: &[&str] = &["AudioVideo", "Audio", "Video", "Development", "Education", "Game", "Graphics", "Network", "Office", "Science", "Settings", "System", "Utility"];
+
+/// Works out the `Categories` of an app from its type and the names of its genres and tags.
+///
+/// `mapping` is keyed by lowercase names, as `Config::parse` leaves them, and takes precedence over `DEFAULT_CATEGORIES`. An empty category drops a name.
+/// Applications get the main categories their genres map to, or `Utility`. Everything else is a `Game`, along with the additional categories its genres map to.
+pub fn categories_for(app_type: Option<&AppType>, labels: &[String], mapping: &HashMap<String, String>) -> Vec<String> {
+    let mut mapped: Vec<&str> = Vec::new();
+    for label in labels {
+        let category = mapping.get(&label.to_ascii_lowercase())
+            .map(String::as_str)
+            .or_else(|| DEFAULT_CATEGORIES.iter().find(|(name, _)| name.eq_ignore_ascii_case(label)).map(|(_, category)| *category));
+
+        match category {
+            Some(category) if !category.is_empty() && !mapped.contains(&category) => mapped.push(category),
+            _ => {}
+        }
+    }
+
+    let (main, additional): (Vec<&str>, Vec<&str>) = mapped.into_iter().partition(|c| MAIN_CATEGORIES.contains(c));
+
+    let categories = match app_type {
+        Some(AppType::Application) if main.is_empty() => vec!["Utility"],
+        Some(AppType::Application) => main,
+        _ => std::iter::once("Game").chain(additional).collect()
+    };
+
+    categories.into_iter().map(String::from).collect()
+}
+
+/// Works out the `Keywords` of an app, which are its tags followed by any genres that aren't tags too.
+pub fn keywords_for(tags: &[String], genres: &[String]) -> Vec<String> {
+    let mut keywords: Vec<String> = Vec::new();
+    for label in tags.iter().chain(genres) {
+        if !keywords.iter().any(|k| k.eq_ignore_ascii_case(label)) {
+            keywords.push(label.clone());
+        }
+    }
+
+    keywords
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn labels(names: &[&str]) -> Vec<String> {
+        names.iter().map(|n| n.to_string()).collect()
+    }
+
+    #[test]
+    fn maps_labels_case_insensitively() {
+        let mapping = HashMap::from([("survival".to_string(), "ActionGame".to_string()), ("rpg".to_string(), String::new())]);
+
+        assert_eq!(categories_for(Some(&AppType::Game), &labels(&["SURVIVAL", "rpg", "strategy", "Unknown"]), &mapping), ["Game", "ActionGame", "StrategyGame"]);
+        assert_eq!(categories_for(Some(&AppType::Application), &labels(&["Photo Editing", "Action"]), &mapping), ["Graphics"]);
+        assert_eq!(categories_for(Some(&AppType::Application), &labels(&["Action"]), &mapping), ["Utility"]);
+    }
+
+    #[test]
+    fn keywords_skip_repeated_labels() {
+        assert_eq!(keywords_for(&labels(&["Survival", "Indie"]), &labels(&["indie", "Action"])), ["Survival", "Indie", "Action"]);
+    }
+}
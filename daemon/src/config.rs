@@ -0,0 +1,693 @@
+//! The daemon's configuration file.
+//!
+//! Settings are read from `$XDG_CONFIG_HOME/steam-shortcut-sync/config.toml`, and every one of them is optional:
+//!
+//! ```toml
+//! # Which games get launchers, "shortcuts" or "installed-apps"
+//! source = "shortcuts"
+//! # A directory whose .desktop files are added to Steam
+//! export_dir = "~/Games/Launchers"
+//! # Where launchers and icons are written
+//! applications_dir = "~/.local/share/applications"
+//! icons_dir = "~/.local/share/icons/hicolor"
+//! # Seconds between checks for requests, which is also how long shutdown can take
+//! poll_interval = 7
+//! # Seconds file changes are collected for before a synchronization
+//! debounce = 10
+//! # How launchers start games: "auto" runs the Steam the game belongs to, or "xdg-open", "native", "flatpak" or "snap"
+//! launch = "auto"
+//! # A command replacing the preset, with {id}, {url}, {name} and {steam_cmd} replaced for each game
+//! exec_template = "{steam_cmd} -silent {url}"
+//! # Commands launchers run Steam through, word by word
+//! wrapper = ["gamemoderun"]
+//! # Whether launchers of games that are gone from Steam are removed
+//! remove_launchers = true
+//! # Whether icons are made from other artwork when Steam didn't install any
+//! fallback_icons = true
+//! # The entries of the launchers' context menus, any of "library", "store", "community", "screenshots", "properties" and "uninstall"
+//! actions = ["library", "store", "community", "screenshots", "properties", "uninstall"]
+//! # Whether the window classes of running games are learned, so docks can tie their windows to launchers
+//! learn_wm_classes = false
+//! # Whether a steam:// handler for a discovered Steam is installed when launchers open those URLs and nothing installed handles them
+//! register_url_handler = true
+//!
+//! # Changes to the launchers of single games, keyed by app id
+//! [overrides.782330]
+//! name = "Doom Eternal"
+//! icon = "~/Pictures/doom.png"
+//! args = "-skipintro"
+//! wm_class = "doometernal"
+//! wrapper = ["systemd-run", "--user", "--scope", "-p", "MemoryMax=8G", "gamemoderun"]
+//! env = { DXVK_HUD = "fps" }
+//!
+//! # Environment variables of every launcher
+//! [env]
+//! MANGOHUD = "1"
+//!
+//! [overrides.1147560]
+//! hidden = true
+//!
+//! # Menu categories of Steam genres and tags, on top of the built-in ones
+//! [categories]
+//! "Survival" = "ActionGame"
+//! "Indie" = ""
+//!
+//! # Rules deciding which apps get launchers, see the filter module
+//! [[filters]]
+//! action = "deny"
+//! types = ["tool"]
+//! ```
+
+use std::{borrow::Cow, collections::{BTreeMap, HashMap, HashSet}, env::{self, VarError}, fmt, fs, io, path::{Path, PathBuf}, sync::{Arc, RwLock}, time::Duration};
+
+use lazy_static::lazy_static;
+use regex::{Captures, Regex};
+use serde::Deserialize;
+
+use crate::{appinfo::AppType, desktop, filter::{Filter, FilterRule}, steam::SteamInstallKind, LauncherAction, SyncSource};
+
+/// Everything that controls what the daemon does.
+///
+/// A `Config` can be loaded from a file or built directly, starting from `Config::default()`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Which games get launchers.
+    pub source: SyncSource,
+    /// A directory whose `.desktop` files are added to Steam as non-Steam shortcuts.
+    /// Files tagged with `X-SteamShortcutSync-Export=true` in the applications directory are added regardless.
+    pub export_dir: Option<PathBuf>,
+    /// Where launchers are written, `$HOME/.local/share/applications` if not set.
+    pub applications_dir: Option<PathBuf>,
+    /// The icon theme icons are installed into, `$XDG_DATA_HOME/icons/hicolor` if not set.
+    pub icons_dir: Option<PathBuf>,
+    /// How many seconds threads sleep between checking for requests. This also bounds how long shutdown takes.
+    pub poll_interval: u64,
+    /// How many seconds file changes are collected for before a synchronization is triggered.
+    pub debounce: u64,
+    /// Which Steam launchers start, and how unless `exec_template` is set.
+    pub launch: LaunchPreset,
+    /// The `Exec` of every launcher, replacing the `launch` preset. See `Config::exec_for` for its placeholders.
+    pub exec_template: Option<String>,
+    /// The command and arguments every launcher runs Steam through, such as `gamemoderun`.
+    pub wrapper: Vec<String>,
+    /// Environment variables every launcher sets for Steam.
+    pub env: BTreeMap<String, String>,
+    /// Whether launchers of games that are no longer in Steam are removed.
+    pub remove_launchers: bool,
+    /// Whether icons are made from library artwork when Steam didn't install any.
+    pub fallback_icons: bool,
+    /// The entries of each launcher's context menu, in order.
+    pub actions: Vec<LauncherAction>,
+    /// Whether the window classes of running games are learned for `StartupWMClass`, for games that don't use `steam_app_<id>`.
+    pub learn_wm_classes: bool,
+    /// Whether a `steam://` handler is registered when launchers open URLs that would otherwise open nothing. See the `url_handler` module.
+    pub register_url_handler: bool,
+    /// Changes to the launchers of single games, keyed by app id.
+    /// Non-Steam shortcuts are keyed by the 64-bit game id their launchers use.
+    pub overrides: HashMap<String, GameOverride>,
+    /// Rules deciding which apps get launchers, checked in order.
+    pub filters: Vec<FilterRule>,
+    /// Freedesktop categories of Steam genres and store tags, taking precedence over `categories::DEFAULT_CATEGORIES`.
+    /// An empty category drops a genre or tag from the defaults. Names match case-insensitively, so they are lowercased when loaded.
+    pub categories: HashMap<String, String>,
+}
+
+/// The ways launchers can start Steam.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LaunchPreset {
+    /// Runs the Steam installation the game was found in directly.
+    Auto,
+    /// Opens the URL with whatever handles `steam://`, which is what Steam's own launchers do.
+    XdgOpen,
+    /// Runs the native `steam`.
+    Native,
+    /// Runs the Steam Flatpak.
+    Flatpak,
+    /// Runs the Steam Snap.
+    Snap,
+}
+
+impl LaunchPreset {
+    /// The `exec_template` equivalent to the preset.
+    pub fn template(&self) -> &'static str {
+        match self {
+            LaunchPreset::XdgOpen => "xdg-open {url}",
+            _ => "{steam_cmd} {url}",
+        }
+    }
+
+    /// The Steam `{steam_cmd}` runs, or `None` if it runs the one the game was found in.
+    pub fn kind(&self) -> Option<SteamInstallKind> {
+        match self {
+            LaunchPreset::Auto | LaunchPreset::XdgOpen => None,
+            LaunchPreset::Native => Some(SteamInstallKind::Native),
+            LaunchPreset::Flatpak => Some(SteamInstallKind::Flatpak),
+            LaunchPreset::Snap => Some(SteamInstallKind::Snap),
+        }
+    }
+}
+
+/// The game a command is made for.
+#[derive(Debug, Clone, Copy)]
+pub struct ExecTarget<'a> {
+    /// The app id, or game id for non-Steam shortcuts.
+    pub id: &'a str,
+    /// The name of the launcher.
+    pub name: &'a str,
+    /// The kind of Steam installation the game was found in.
+    pub kind: SteamInstallKind,
+}
+
+/// Changes to the launcher of one game, applied every time it is written.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GameOverride {
+    /// Replaces the name from Steam, in every language.
+    pub name: Option<String>,
+    /// An icon name or image path used instead of the icons from Steam, which then aren't installed.
+    pub icon: Option<String>,
+    /// Launch options passed to the game with `steam://run/<id>//<args>`.
+    pub args: Option<String>,
+    /// Keeps the launcher out of menus by setting `NoDisplay`.
+    pub hidden: bool,
+    /// The class of the game's windows, for native games that don't use `steam_app_<id>`.
+    pub wm_class: Option<String>,
+    /// Replaces the global `wrapper`, where an empty list runs Steam directly.
+    pub wrapper: Option<Vec<String>>,
+    /// Environment variables set on top of the global ones.
+    pub env: BTreeMap<String, String>,
+}
+
+/// Settings given on the command line, which take precedence over the configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Overrides {
+    pub source: Option<SyncSource>,
+    pub export_dir: Option<PathBuf>,
+}
+
+impl Overrides {
+    /// Replaces the settings of `config` that were overridden.
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(source) = self.source {
+            config.source = source;
+        }
+        if self.export_dir.is_some() {
+            config.export_dir = self.export_dir.clone();
+        }
+    }
+}
+
+/// The active `Config` of a running daemon, shared between its threads.
+///
+/// Reloading swaps in a whole new `Config`, so a thread that got the active one keeps a consistent view of it until it asks again.
+#[derive(Debug, Clone)]
+pub struct SharedConfig {
+    active: Arc<RwLock<Arc<Config>>>,
+    path: Option<PathBuf>,
+    overrides: Overrides,
+}
+
+impl SharedConfig {
+    /// Shares `config`, which is reloaded from `path` with `overrides` applied when asked to.
+    /// Without a `path` the configuration never changes.
+    pub fn new(config: Config, path: Option<PathBuf>, overrides: Overrides) -> SharedConfig {
+        // Relative paths would break once the working directory changes
+        let path = path.map(|p| env::current_dir().map(|d| d.join(&p)).unwrap_or(p));
+
+        SharedConfig { active: Arc::new(RwLock::new(Arc::new(config))), path, overrides }
+    }
+
+    /// The active configuration.
+    pub fn get(&self) -> Arc<Config> {
+        Arc::clone(&self.active.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// The file the configuration is reloaded from.
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Loads the file again and makes it the active configuration, if it is valid.
+    ///
+    /// A missing file counts as one with only defaults.
+    /// Returns the previous configuration if anything changed, and leaves the active one in place on errors.
+    pub fn reload(&self) -> Result<Option<Arc<Config>>, ConfigError> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(None)
+        };
+
+        let mut config = match fs::metadata(path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Config::default(),
+            _ => Config::load_from(path)?
+        };
+        self.overrides.apply(&mut config);
+        config.validate()?;
+
+        let mut active = self.active.write().unwrap_or_else(|e| e.into_inner());
+        if **active == config {
+            return Ok(None);
+        }
+
+        Ok(Some(std::mem::replace(&mut *active, Arc::new(config))))
+    }
+}
+
+/// Possible errors when loading a `Config`.
+#[derive(Debug)]
+pub struct ConfigError {
+    /// The specific kind of error.
+    pub kind: ConfigErrorKind
+}
+
+#[derive(Debug)]
+pub enum ConfigErrorKind {
+    /// Neither `XDG_CONFIG_HOME` nor `HOME` is defined.
+    NoConfigDir,
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file isn't valid TOML, or has unknown keys or values of the wrong type.
+    Parse(String),
+    /// A value has the right type but isn't allowed.
+    Invalid { key: &'static str, reason: &'static str },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            ConfigErrorKind::NoConfigDir => write!(f, "neither XDG_CONFIG_HOME nor HOME is defined"),
+            ConfigErrorKind::Io(e) => write!(f, "{}", e),
+            ConfigErrorKind::Parse(e) => write!(f, "{}", e.trim_end()),
+            ConfigErrorKind::Invalid { key, reason } => write!(f, "invalid value for {}: {}", key, reason),
+        }
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            source: SyncSource::Shortcuts,
+            export_dir: None,
+            applications_dir: None,
+            icons_dir: None,
+            poll_interval: 5,
+            debounce: 10,
+            launch: LaunchPreset::Auto,
+            exec_template: None,
+            wrapper: Vec::new(),
+            env: BTreeMap::new(),
+            remove_launchers: true,
+            fallback_icons: true,
+            actions: LauncherAction::ALL.to_vec(),
+            learn_wm_classes: false,
+            register_url_handler: true,
+            overrides: HashMap::new(),
+            filters: Vec::new(),
+            categories: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration from its default location, or the defaults if there is no file.
+    pub fn load() -> Result<Config, ConfigError> {
+        let path = config_path().map_err(|_| ConfigError { kind: ConfigErrorKind::NoConfigDir })?;
+
+        match fs::metadata(&path) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            _ => Self::load_from(&path)
+        }
+    }
+
+    /// Loads the configuration stored at `path`.
+    pub fn load_from(path: &Path) -> Result<Config, ConfigError> {
+        let contents = fs::read_to_string(path).map_err(|e| ConfigError { kind: ConfigErrorKind::Io(e) })?;
+
+        Self::parse(&contents)
+    }
+
+    /// Parses and validates a configuration, expanding a leading `~/` in paths to the home directory.
+    pub fn parse(contents: &str) -> Result<Config, ConfigError> {
+        let mut config: Config = toml::from_str(contents).map_err(|e| ConfigError { kind: ConfigErrorKind::Parse(e.to_string()) })?;
+
+        for path in [&mut config.export_dir, &mut config.applications_dir, &mut config.icons_dir].into_iter().flatten() {
+            if let Ok(rest) = path.strip_prefix("~") {
+                if let Ok(home) = env::var("HOME") {
+                    *path = Path::new(&home).join(rest);
+                }
+            }
+        }
+        for path in config.filters.iter_mut().filter_map(|f| f.library.as_mut()) {
+            if let (Ok(rest), Ok(home)) = (path.strip_prefix("~"), env::var("HOME")) {
+                *path = Path::new(&home).join(rest);
+            }
+        }
+        for icon in config.overrides.values_mut().filter_map(|o| o.icon.as_mut()) {
+            if let (Some(rest), Ok(home)) = (icon.strip_prefix("~/"), env::var("HOME")) {
+                *icon = Path::new(&home).join(rest).to_string_lossy().into_owned();
+            }
+        }
+
+        config.validate()?;
+        // Only after validating, which rejects names that would collide
+        config.categories = config.categories.into_iter().map(|(name, category)| (name.to_ascii_lowercase(), category)).collect();
+
+        Ok(config)
+    }
+
+    /// Checks the values that their types alone don't restrict enough.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |key, reason| Err(ConfigError { kind: ConfigErrorKind::Invalid { key, reason } });
+
+        if self.poll_interval == 0 {
+            return invalid("poll_interval", "must be at least 1 second");
+        }
+        if self.exec_template.as_ref().map(|t| !t.contains("{id}") && !t.contains("{url}")).unwrap_or(false) {
+            return invalid("exec_template", "must contain {id} or {url}");
+        }
+
+        let games = self.overrides.values();
+        if self.wrapper.iter().chain(games.clone().filter_map(|o| o.wrapper.as_ref()).flatten()).any(|w| w.is_empty()) {
+            return invalid("wrapper", "must not contain empty words");
+        }
+        if self.env.keys().chain(games.flat_map(|o| o.env.keys())).any(|k| !is_env_name(k)) {
+            return invalid("env", "must be keyed by environment variable names");
+        }
+
+        let paths = [("export_dir", &self.export_dir), ("applications_dir", &self.applications_dir), ("icons_dir", &self.icons_dir)];
+        for (key, path) in paths {
+            if path.as_ref().map(|p| !p.is_absolute()).unwrap_or(false) {
+                return invalid(key, "must be an absolute path or start with ~/");
+            }
+        }
+
+        if self.overrides.keys().any(|id| id.is_empty() || !id.bytes().all(|b| b.is_ascii_digit())) {
+            return invalid("overrides", "must be keyed by app id");
+        }
+        // Game ids of non-Steam shortcuts don't fit into `steam://run/<id>//<args>`
+        if self.overrides.iter().any(|(id, o)| id.parse::<u32>().is_err() && o.args.as_deref().map(|a| !a.trim().is_empty()).unwrap_or(false)) {
+            return invalid("overrides", "args only work for Steam games, set the launch options of non-Steam shortcuts in Steam");
+        }
+
+        if self.categories.values().any(|c| !c.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')) {
+            return invalid("categories", "must map to freedesktop category names");
+        }
+        let names: HashSet<String> = self.categories.keys().map(|n| n.to_ascii_lowercase()).collect();
+        if names.len() != self.categories.len() {
+            return invalid("categories", "must not list a name twice in different case");
+        }
+
+        if Filter::new(&self.filters).is_err() {
+            return invalid("filters", "name must be a valid regular expression");
+        }
+        if self.filters.iter().any(|f| f.library.as_ref().map(|p| !p.is_absolute()).unwrap_or(false)) {
+            return invalid("filters", "library must be an absolute path or start with ~/");
+        }
+        if self.filters.iter().flat_map(|f| &f.ids).any(|id| id.parse::<u64>().is_err()) {
+            return invalid("filters", "ids must be app ids or game ids");
+        }
+        if self.filters.iter().flat_map(|f| &f.types).any(|t| matches!(AppType::parse(t), AppType::Other(_))) {
+            return invalid("filters", "types must be game, application, tool, demo, dlc, music, video or config");
+        }
+
+        Ok(())
+    }
+
+    /// How long threads sleep between checking for requests.
+    pub fn poll_interval(&self) -> Duration {
+        Duration::from_secs(self.poll_interval)
+    }
+
+    /// How long file changes are collected for before a synchronization is triggered.
+    pub fn debounce(&self) -> Duration {
+        Duration::from_secs(self.debounce)
+    }
+
+    /// Returns `true` if launchers made with `other` could differ from ones made with this configuration.
+    pub fn affects_output(&self, other: &Config) -> bool {
+        let without_timings = |c: &Config| Config { poll_interval: 0, debounce: 0, ..c.clone() };
+
+        without_timings(self) != without_timings(other)
+    }
+
+    /// Returns `true` if launchers or their actions open `steam://` URLs through whatever handles them, rather than by running Steam.
+    ///
+    /// That is the case for `launch = "xdg-open"` and for templates that use `xdg-open` or pass `{url}` to anything but `{steam_cmd}`.
+    pub fn uses_url_handler(&self) -> bool {
+        let template = self.template();
+
+        template.contains("xdg-open") || (template.contains("{url}") && !template.contains("{steam_cmd}"))
+    }
+
+    /// The command that opens a `steam://` URL for `target` the same way its launcher starts it.
+    ///
+    /// Templates that only use `{id}` open URLs with the Steam `target` was found in instead.
+    pub fn open_url(&self, target: &ExecTarget, url: &str) -> String {
+        let template = self.template();
+        let template = if template.contains("{url}") { template } else { Cow::Borrowed(LaunchPreset::Auto.template()) };
+        let steam_cmd = self.launch.kind().unwrap_or(target.kind).steam_command();
+
+        fill_template(&template, target, steam_cmd, url)
+    }
+
+    /// The `Exec` of the launcher for `target`.
+    ///
+    /// The template replaces `{id}` with the app id, `{url}` with the URL that starts the game, `{name}` with the name of the launcher and `{steam_cmd}` with the command that runs the Steam the game was found in.
+    /// Launch options from its override turn the URL into `steam://run/<id>//<args>`, or are appended to templates that only use `{id}`.
+    /// Non-Steam shortcuts only start with `steam://rungameid/`, so `validate` rejects launch options for them.
+    /// The command is run through the wrapper and with the environment variables of the game, which are passed with `--env=` when `{steam_cmd}` is the Flatpak.
+    pub fn exec_for(&self, target: &ExecTarget) -> String {
+        let game = self.overrides.get(target.id);
+        let args = game.and_then(|o| o.args.as_deref()).map(str::trim).filter(|a| !a.is_empty());
+        let url = match args {
+            Some(args) if target.id.parse::<u32>().is_ok() => format!("steam://run/{}//{}", target.id, percent_encode(args)),
+            _ => format!("steam://rungameid/{}", target.id)
+        };
+
+        let mut env = self.env.clone();
+        env.extend(game.map(|o| o.env.clone()).unwrap_or_default());
+        let env: Vec<String> = env.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+
+        let template = self.template();
+        let kind = self.launch.kind().unwrap_or(target.kind);
+        // Flatpak filters the environment it passes into the sandbox, but not variables given with `--env=`
+        let flatpak_env = kind == SteamInstallKind::Flatpak && template.contains("{steam_cmd}") && !env.is_empty();
+        let steam_cmd = if flatpak_env {
+            let env: Vec<String> = env.iter().map(|e| desktop::quote_exec_arg(&format!("--env={}", e))).collect();
+            kind.steam_command().replacen("flatpak run", &format!("flatpak run {}", env.join(" ")), 1)
+        } else {
+            kind.steam_command().to_string()
+        };
+
+        let mut words = Vec::new();
+        if !env.is_empty() && !flatpak_env {
+            words.push("env".to_string());
+            words.extend(env.iter().map(|e| desktop::quote_exec_arg(e)));
+        }
+        words.extend(game.and_then(|o| o.wrapper.as_ref()).unwrap_or(&self.wrapper).iter().map(|w| desktop::quote_exec_arg(w)));
+        words.push(fill_template(&template, target, &steam_cmd, &url));
+        if let Some(args) = args.filter(|_| !template.contains("{url}")) {
+            words.extend(args.split_whitespace().map(desktop::quote_exec_arg));
+        }
+
+        words.join(" ")
+    }
+
+    // Templates from before `{url}` existed spell the URL out
+    fn template(&self) -> Cow<'_, str> {
+        match &self.exec_template {
+            Some(template) => Cow::Owned(template.replace("steam://rungameid/{id}", "{url}")),
+            None => Cow::Borrowed(self.launch.template())
+        }
+    }
+}
+
+// Replaces every placeholder in one pass, so nothing that was filled in is replaced again
+fn fill_template(template: &str, target: &ExecTarget, steam_cmd: &str, url: &str) -> String {
+    lazy_static! {
+        static ref PLACEHOLDER_REGEX: Regex = Regex::new(r"\{(id|url|name|steam_cmd)\}").unwrap();
+    };
+
+    PLACEHOLDER_REGEX.replace_all(template, |caps: &Captures| match &caps[1] {
+        "id" => target.id.to_string(),
+        "url" => desktop::quote_exec_arg(url),
+        "name" => desktop::quote_exec_arg(target.name),
+        _ => steam_cmd.to_string()
+    }).into_owned()
+}
+
+// Steam decodes the launch options of `steam://run/` URLs, so anything but unreserved characters is escaped
+fn percent_encode(value: &str) -> String {
+    value.bytes().map(|b| match b {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (b as char).to_string(),
+        b => format!("%{:02X}", b)
+    }).collect()
+}
+
+// Names `env` and `flatpak run --env=` accept without surprises
+fn is_env_name(name: &str) -> bool {
+    !name.is_empty() && !name.starts_with(|c: char| c.is_ascii_digit()) && name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_')
+}
+
+/// Where the configuration file is, `$XDG_CONFIG_HOME/steam-shortcut-sync/config.toml`.
+pub fn config_path() -> Result<PathBuf, VarError> {
+    let base = match env::var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => Path::new(&env::var("HOME")?).join(".config")
+    };
+
+    Ok(base.join("steam-shortcut-sync/config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn invalid_key(contents: &str) -> Option<&'static str> {
+        match Config::parse(contents) {
+            Err(ConfigError { kind: ConfigErrorKind::Invalid { key, .. } }) => Some(key),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn validates_filters() {
+        let config = Config::parse("[[filters]]\naction = \"deny\"\nids = [\"12501462953196781568\"]\ntypes = [\"Game\", \"dlc\"]").unwrap();
+        assert_eq!(config.filters[0].ids, ["12501462953196781568"]);
+
+        assert_eq!(invalid_key("[[filters]]\naction = \"deny\"\nids = [\"not an id\"]"), Some("filters"));
+        assert_eq!(invalid_key("[[filters]]\naction = \"deny\"\nids = [\"18446744073709551616\"]"), Some("filters"));
+        assert_eq!(invalid_key("[[filters]]\naction = \"deny\"\ntypes = [\"games\"]"), Some("filters"));
+        assert_eq!(invalid_key("[[filters]]\naction = \"deny\"\nname = \"(\""), Some("filters"));
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        for contents in ["fallback_icon = true", "[overrides.10]\nnmae = \"Game\"", "[[filters]]\naction = \"deny\"\nid = [\"10\"]", "source = \"everything\""] {
+            assert!(matches!(Config::parse(contents).unwrap_err().kind, ConfigErrorKind::Parse(_)), "{}", contents);
+        }
+    }
+
+    #[test]
+    fn expands_the_home_directory() {
+        let home = PathBuf::from(env::var("HOME").unwrap());
+        let config = Config::parse("export_dir = \"~/Games\"\nicons_dir = \"~\"\n[overrides.10]\nicon = \"~/doom.png\"\n[[filters]]\naction = \"deny\"\nlibrary = \"~/Library\"").unwrap();
+
+        assert_eq!(config.export_dir, Some(home.join("Games")));
+        assert_eq!(config.icons_dir, Some(home.clone()));
+        assert_eq!(config.overrides["10"].icon, Some(home.join("doom.png").to_string_lossy().into_owned()));
+        assert_eq!(config.filters[0].library, Some(home.join("Library")));
+
+        // Only the current user's home directory is known
+        assert_eq!(invalid_key("export_dir = \"~other/Games\""), Some("export_dir"));
+    }
+
+    #[test]
+    fn rejects_relative_paths() {
+        assert_eq!(invalid_key("export_dir = \"Games\""), Some("export_dir"));
+        assert_eq!(invalid_key("applications_dir = \"./applications\""), Some("applications_dir"));
+        assert_eq!(invalid_key("icons_dir = \"\""), Some("icons_dir"));
+        assert_eq!(invalid_key("[[filters]]\naction = \"deny\"\nlibrary = \"Library\""), Some("filters"));
+        assert!(Config::parse("export_dir = \"/srv/games\"").is_ok());
+    }
+
+    #[test]
+    fn reloads_with_overrides_and_keeps_the_config_on_errors() {
+        let dir = env::temp_dir().join(format!("steam-shortcut-sync-reload-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.toml");
+        let overrides = Overrides { source: Some(SyncSource::InstalledApps), export_dir: Some(PathBuf::from("/srv/export")) };
+
+        let config = SharedConfig::new(Config::default(), Some(path.clone()), overrides);
+        // A missing file has only defaults, with the command line applied on top
+        assert_eq!(config.reload().unwrap().map(|c| c.source), Some(SyncSource::Shortcuts));
+        assert_eq!(config.get().source, SyncSource::InstalledApps);
+        assert!(config.reload().unwrap().is_none());
+
+        fs::write(&path, "source = \"shortcuts\"\nexport_dir = \"/srv/other\"\npoll_interval = 7").unwrap();
+        assert!(config.reload().unwrap().is_some());
+        assert_eq!((config.get().source, config.get().export_dir.clone(), config.get().poll_interval), (SyncSource::InstalledApps, Some(PathBuf::from("/srv/export")), 7));
+
+        for invalid in ["poll_interval = 0", "poll_interval = ", "unknown = true"] {
+            fs::write(&path, invalid).unwrap();
+            assert!(config.reload().is_err(), "{}", invalid);
+            assert_eq!(config.get().poll_interval, 7);
+        }
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn exec(config: &str, id: &str, kind: SteamInstallKind) -> String {
+        let config = Config::parse(config).unwrap();
+        config.exec_for(&ExecTarget { id, name: "My Game", kind })
+    }
+
+    #[test]
+    fn exec_for_presets() {
+        assert_eq!(exec("", "10", SteamInstallKind::Native), "steam steam://rungameid/10");
+        assert_eq!(exec("", "10", SteamInstallKind::Flatpak), "flatpak run com.valvesoftware.Steam steam://rungameid/10");
+        assert_eq!(exec("launch = \"auto\"", "10", SteamInstallKind::Snap), "snap run steam steam://rungameid/10");
+        assert_eq!(exec("launch = \"xdg-open\"", "10", SteamInstallKind::Flatpak), "xdg-open steam://rungameid/10");
+        assert_eq!(exec("launch = \"native\"", "10", SteamInstallKind::Flatpak), "steam steam://rungameid/10");
+        assert_eq!(exec("launch = \"flatpak\"", "10", SteamInstallKind::Native), "flatpak run com.valvesoftware.Steam steam://rungameid/10");
+        assert_eq!(exec("launch = \"snap\"", "10", SteamInstallKind::Native), "snap run steam steam://rungameid/10");
+    }
+
+    #[test]
+    fn exec_for_templates() {
+        assert_eq!(exec("exec_template = \"gamemoderun {steam_cmd} -silent {url} # {name}\"", "10", SteamInstallKind::Native), "gamemoderun steam -silent steam://rungameid/10 # \"My Game\"");
+        assert_eq!(exec("exec_template = \"steam steam://rungameid/{id}\"", "10", SteamInstallKind::Native), "steam steam://rungameid/10");
+        assert_eq!(exec("exec_template = \"run-game {id}\"\n[overrides.10]\nargs = \"-novid -w 1920\"", "10", SteamInstallKind::Native), "run-game 10 -novid -w 1920");
+    }
+
+    #[test]
+    fn exec_for_args() {
+        let config = "[overrides.10]\nargs = \" -novid +connect 1.2.3.4:27015 -name \\\"A B\\\" 100% \"";
+        // `%` is doubled once more, since `Exec` treats it as the start of a field code
+        assert_eq!(exec(config, "10", SteamInstallKind::Native), "steam steam://run/10//-novid%%20%%2Bconnect%%201.2.3.4%%3A27015%%20-name%%20%%22A%%20B%%22%%20100%%25");
+
+        // Non-Steam shortcuts can't take launch options through a URL
+        assert_eq!(invalid_key("[overrides.12501462953196781568]\nargs = \"-novid\""), Some("overrides"));
+        assert!(Config::parse("[overrides.12501462953196781568]\nname = \"Renamed\"").is_ok());
+        assert_eq!(exec("", "12501462953196781568", SteamInstallKind::Native), "steam steam://rungameid/12501462953196781568");
+    }
+
+    #[test]
+    fn exec_for_wrappers_and_env() {
+        let config = "wrapper = [\"systemd-run\", \"--user\", \"--scope\", \"-p\", \"MemoryMax=8 G\"]\nenv = { DXVK_HUD = \"fps\", MANGOHUD = \"1\" }\n[overrides.20]\nwrapper = [\"gamemoderun\"]\nenv = { DXVK_HUD = \"full\" }\n[overrides.30]\nwrapper = []";
+
+        assert_eq!(exec(config, "10", SteamInstallKind::Native), "env DXVK_HUD=fps MANGOHUD=1 systemd-run --user --scope -p \"MemoryMax=8 G\" steam steam://rungameid/10");
+        assert_eq!(exec(config, "20", SteamInstallKind::Native), "env DXVK_HUD=full MANGOHUD=1 gamemoderun steam steam://rungameid/20");
+        assert_eq!(exec(config, "30", SteamInstallKind::Snap), "env DXVK_HUD=fps MANGOHUD=1 snap run steam steam://rungameid/30");
+        // Flatpak only sees variables passed with `--env=`
+        assert_eq!(exec(config, "20", SteamInstallKind::Flatpak), "gamemoderun flatpak run --env=DXVK_HUD=full --env=MANGOHUD=1 com.valvesoftware.Steam steam://rungameid/20");
+        assert_eq!(exec(&format!("launch = \"xdg-open\"\n{}", config), "20", SteamInstallKind::Flatpak), "env DXVK_HUD=full MANGOHUD=1 gamemoderun xdg-open steam://rungameid/20");
+
+        assert_eq!(invalid_key("wrapper = [\"\"]"), Some("wrapper"));
+        assert_eq!(invalid_key("env = { \"1A\" = \"x\" }"), Some("env"));
+        assert_eq!(invalid_key("[overrides.10]\nenv = { \"A-B\" = \"x\" }"), Some("env"));
+    }
+
+    #[test]
+    fn only_uses_url_handler_when_opening_urls() {
+        assert!(!Config::default().uses_url_handler());
+        assert!(!Config::parse("launch = \"flatpak\"").unwrap().uses_url_handler());
+        assert!(!Config::parse("exec_template = \"gamemoderun {steam_cmd} {url}\"").unwrap().uses_url_handler());
+        assert!(!Config::parse("exec_template = \"run-game {id}\"").unwrap().uses_url_handler());
+        assert!(Config::parse("launch = \"xdg-open\"").unwrap().uses_url_handler());
+        assert!(Config::parse("exec_template = \"xdg-open steam://rungameid/{id}\"").unwrap().uses_url_handler());
+        assert!(Config::parse("exec_template = \"gio open {url}\"").unwrap().uses_url_handler());
+    }
+
+    #[test]
+    fn lowercases_category_names() {
+        let config = Config::parse("[categories]\n\"Survival\" = \"ActionGame\"\n\"indie\" = \"\"").unwrap();
+        assert_eq!(config.categories.get("survival").map(String::as_str), Some("ActionGame"));
+        assert_eq!(config.categories.get("indie").map(String::as_str), Some(""));
+
+        assert_eq!(invalid_key("[categories]\n\"Survival\" = \"ActionGame\"\n\"survival\" = \"\""), Some("categories"));
+    }
+}
@@ -0,0 +1,668 @@
+//! A parser and writer for freedesktop Desktop Entry files.
+//!
+//! Files are kept line by line, so anything that isn't changed, including comments and spacing, is written back exactly as it was read.
+
+use std::fmt;
+
+/// The main group every desktop entry must have.
+pub const DESKTOP_ENTRY_GROUP: &str = "Desktop Entry";
+
+/// What the names of the groups defining the entries of `Actions` start with, followed by the action's id.
+pub const DESKTOP_ACTION_PREFIX: &str = "Desktop Action ";
+
+/// A parsed `.desktop` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DesktopEntry {
+    /// Comments and blank lines before the first group.
+    header: Vec<String>,
+    groups: Vec<Group>,
+    trailing_newline: bool,
+}
+
+/// A `[Group Name]` section and its entries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Group {
+    name: String,
+    /// The original header line, for groups that were parsed.
+    header: Option<String>,
+    lines: Vec<GroupLine>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum GroupLine {
+    /// A comment or blank line.
+    Other(String),
+    Entry {
+        key: String,
+        locale: Option<String>,
+        /// The value as written in the file, with escapes intact.
+        value: String,
+        /// The original line, until the entry is modified.
+        raw: Option<String>,
+    },
+}
+
+/// Possible errors when parsing a desktop entry.
+#[derive(Debug, Clone)]
+pub struct DesktopParseError {
+    /// The specific kind of error.
+    pub kind: DesktopParseErrorKind,
+    /// The line the error occured on, starting at 1.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum DesktopParseErrorKind {
+    /// A key-value pair appeared before any group header.
+    EntryOutsideGroup,
+    /// A line was neither a comment, a group header nor a key-value pair.
+    InvalidLine,
+    /// The same group header appeared twice.
+    DuplicateGroup(String),
+    /// The same key, with the same locale, appeared twice in one group.
+    DuplicateKey(String),
+}
+
+impl fmt::Display for DesktopParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            DesktopParseErrorKind::EntryOutsideGroup => write!(f, "entry outside of a group on line {}", self.line),
+            DesktopParseErrorKind::InvalidLine => write!(f, "invalid line {}", self.line),
+            DesktopParseErrorKind::DuplicateGroup(name) => write!(f, "duplicate group [{}] on line {}", name, self.line),
+            DesktopParseErrorKind::DuplicateKey(key) => write!(f, "duplicate key {} on line {}", key, self.line),
+        }
+    }
+}
+
+impl DesktopEntry {
+    /// Creates an empty file with no groups.
+    pub fn new() -> DesktopEntry {
+        DesktopEntry { header: Vec::new(), groups: Vec::new(), trailing_newline: true }
+    }
+
+    /// Parses the contents of a `.desktop` file.
+    pub fn parse(input: &str) -> Result<DesktopEntry, DesktopParseError> {
+        let mut entry = DesktopEntry {
+            header: Vec::new(),
+            groups: Vec::new(),
+            trailing_newline: input.ends_with('\n'),
+        };
+
+        for (i, line) in input.lines().enumerate() {
+            let error = |kind| DesktopParseError { kind, line: i + 1 };
+            let trimmed = line.trim();
+
+            if trimmed.is_empty() || trimmed.starts_with('#') {
+                match entry.groups.last_mut() {
+                    Some(group) => group.lines.push(GroupLine::Other(line.to_string())),
+                    None => entry.header.push(line.to_string())
+                }
+                continue;
+            }
+
+            if trimmed.starts_with('[') && trimmed.ends_with(']') {
+                let name = &trimmed[1..trimmed.len() - 1];
+                if entry.group(name).is_some() {
+                    return Err(error(DesktopParseErrorKind::DuplicateGroup(name.to_string())));
+                }
+                let mut group = Group::new(name);
+                group.header = Some(line.to_string());
+                entry.groups.push(group);
+                continue;
+            }
+
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim_start()),
+                None => return Err(error(DesktopParseErrorKind::InvalidLine))
+            };
+
+            let (key, locale) = match key.split_once('[') {
+                Some((k, l)) if l.ends_with(']') => (k, Some(l[..l.len() - 1].to_string())),
+                Some(_) => return Err(error(DesktopParseErrorKind::InvalidLine)),
+                None => (key, None)
+            };
+
+            if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+                return Err(error(DesktopParseErrorKind::InvalidLine));
+            }
+
+            let group = entry.groups.last_mut().ok_or_else(|| error(DesktopParseErrorKind::EntryOutsideGroup))?;
+            // Readers disagree on which of two values wins, so there is no safe way to keep both
+            if group.find(key, locale.as_deref()).is_some() {
+                let trimmed = line.split_once('=').map(|(k, _)| k.trim()).unwrap_or(key);
+                return Err(error(DesktopParseErrorKind::DuplicateKey(trimmed.to_string())));
+            }
+            group.lines.push(GroupLine::Entry {
+                key: key.to_string(),
+                locale,
+                value: value.to_string(),
+                raw: Some(line.to_string()),
+            });
+        }
+
+        Ok(entry)
+    }
+
+    /// Returns the group named `name`.
+    pub fn group(&self, name: &str) -> Option<&Group> {
+        self.groups.iter().find(|g| g.name == name)
+    }
+
+    /// Returns the group named `name` for modification.
+    pub fn group_mut(&mut self, name: &str) -> Option<&mut Group> {
+        self.groups.iter_mut().find(|g| g.name == name)
+    }
+
+    /// Returns the group named `name`, appending an empty one if it doesn't exist.
+    pub fn group_or_insert(&mut self, name: &str) -> &mut Group {
+        match self.groups.iter().position(|g| g.name == name) {
+            Some(i) => &mut self.groups[i],
+            None => {
+                self.groups.push(Group::new(name));
+                self.groups.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Removes the group named `name`.
+    pub fn remove_group(&mut self, name: &str) {
+        self.groups.retain(|g| g.name != name);
+    }
+
+    /// Iterates over every group in file order.
+    pub fn groups(&self) -> impl Iterator<Item = &Group> {
+        self.groups.iter()
+    }
+
+    /// The `[Desktop Entry]` group.
+    pub fn desktop_entry(&self) -> Option<&Group> {
+        self.group(DESKTOP_ENTRY_GROUP)
+    }
+
+    /// The `[Desktop Entry]` group for modification, created if it doesn't exist.
+    pub fn desktop_entry_mut(&mut self) -> &mut Group {
+        self.group_or_insert(DESKTOP_ENTRY_GROUP)
+    }
+
+    /// The unlocalized `Name`.
+    pub fn name(&self) -> Option<String> {
+        self.desktop_entry()?.get_string("Name")
+    }
+
+    /// The `Exec` command line, with string escapes resolved.
+    pub fn exec(&self) -> Option<String> {
+        self.desktop_entry()?.get_string("Exec")
+    }
+
+    /// The `Icon` name or path.
+    pub fn icon(&self) -> Option<String> {
+        self.desktop_entry()?.get_string("Icon")
+    }
+
+    /// Whether `Hidden` is set, which means the entry should be treated as deleted.
+    pub fn hidden(&self) -> bool {
+        self.desktop_entry().and_then(|g| g.get_bool("Hidden")).unwrap_or(false)
+    }
+
+    /// Whether `NoDisplay` is set, which means the entry should be kept out of menus.
+    pub fn no_display(&self) -> bool {
+        self.desktop_entry().and_then(|g| g.get_bool("NoDisplay")).unwrap_or(false)
+    }
+
+    /// The ids listed in `Actions`.
+    pub fn actions(&self) -> Vec<String> {
+        self.desktop_entry().and_then(|g| g.get_strings("Actions")).unwrap_or_default()
+    }
+
+    /// The `[Desktop Action <id>]` group of an action.
+    pub fn action(&self, id: &str) -> Option<&Group> {
+        self.group(&format!("{}{}", DESKTOP_ACTION_PREFIX, id))
+    }
+
+    /// Adds an action to `Actions` along with its group, or replaces the `Name` and `Exec` of the one with the same id.
+    pub fn set_action(&mut self, id: &str, name: &str, exec: &str) {
+        let mut actions = self.actions();
+        if !actions.iter().any(|a| a == id) {
+            actions.push(id.to_string());
+            self.desktop_entry_mut().set_strings("Actions", &actions);
+        }
+
+        let group = self.group_or_insert(&format!("{}{}", DESKTOP_ACTION_PREFIX, id));
+        group.set_string("Name", name);
+        group.set_string("Exec", exec);
+    }
+
+    /// Removes every action along with its group, including groups `Actions` doesn't list.
+    pub fn clear_actions(&mut self) {
+        self.groups.retain(|g| !g.name.starts_with(DESKTOP_ACTION_PREFIX));
+        if let Some(group) = self.group_mut(DESKTOP_ENTRY_GROUP) {
+            group.remove("Actions");
+        }
+    }
+}
+
+impl Default for DesktopEntry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for DesktopEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines: Vec<String> = self.header.clone();
+
+        for group in &self.groups {
+            lines.push(group.header.clone().unwrap_or_else(|| format!("[{}]", group.name)));
+            for line in &group.lines {
+                lines.push(match line {
+                    GroupLine::Other(raw) | GroupLine::Entry { raw: Some(raw), .. } => raw.clone(),
+                    GroupLine::Entry { key, locale: Some(locale), value, raw: None } => format!("{}[{}]={}", key, locale, value),
+                    GroupLine::Entry { key, locale: None, value, raw: None } => format!("{}={}", key, value),
+                });
+            }
+        }
+
+        f.write_str(&lines.join("\n"))?;
+        if self.trailing_newline {
+            f.write_str("\n")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Group {
+    fn new(name: &str) -> Group {
+        Group { name: name.to_string(), header: None, lines: Vec::new() }
+    }
+
+    /// The group's name, without brackets.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn find(&self, key: &str, locale: Option<&str>) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            GroupLine::Entry { key: k, locale: l, value, .. } if k == key && l.as_deref() == locale => Some(value.as_str()),
+            _ => None
+        })
+    }
+
+    /// Returns the raw value of an unlocalized key, with escapes intact.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.find(key, None)
+    }
+
+    /// Returns the value of an unlocalized string key.
+    pub fn get_string(&self, key: &str) -> Option<String> {
+        self.get(key).map(unescape)
+    }
+
+    /// Returns the best translation of a localized string key for `locale`, such as `de_DE.UTF-8@euro`.
+    ///
+    /// Follows the spec's matching order of `lang_COUNTRY@MODIFIER`, `lang_COUNTRY`, `lang@MODIFIER`, `lang` and finally the unlocalized key.
+    pub fn get_localized(&self, key: &str, locale: &str) -> Option<String> {
+        // The encoding is never part of the match
+        let (rest, modifier) = match locale.split_once('@') {
+            Some((r, m)) => (r, Some(m)),
+            None => (locale, None)
+        };
+        let rest = rest.split('.').next().unwrap_or(rest);
+        let (lang, country) = match rest.split_once('_') {
+            Some((l, c)) => (l, Some(c)),
+            None => (rest, None)
+        };
+
+        let mut candidates = Vec::new();
+        if let (Some(c), Some(m)) = (country, modifier) {
+            candidates.push(format!("{}_{}@{}", lang, c, m));
+        }
+        if let Some(c) = country {
+            candidates.push(format!("{}_{}", lang, c));
+        }
+        if let Some(m) = modifier {
+            candidates.push(format!("{}@{}", lang, m));
+        }
+        candidates.push(lang.to_string());
+
+        candidates.iter()
+            .find_map(|l| self.find(key, Some(l)))
+            .or_else(|| self.get(key))
+            .map(unescape)
+    }
+
+    /// Returns the value of a boolean key.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key)?.trim() {
+            "true" => Some(true),
+            "false" => Some(false),
+            _ => None
+        }
+    }
+
+    /// Returns the value of a numeric key.
+    pub fn get_number(&self, key: &str) -> Option<f64> {
+        self.get(key)?.trim().parse().ok()
+    }
+
+    /// Returns the items of a semicolon-separated list key.
+    pub fn get_strings(&self, key: &str) -> Option<Vec<String>> {
+        Some(split_list(self.get(key)?))
+    }
+
+    /// Iterates over every key in file order as `(key, locale, raw value)`.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, Option<&str>, &str)> {
+        self.lines.iter().filter_map(|line| match line {
+            GroupLine::Entry { key, locale, value, .. } => Some((key.as_str(), locale.as_deref(), value.as_str())),
+            GroupLine::Other(_) => None
+        })
+    }
+
+    fn set_value(&mut self, key: &str, locale: Option<&str>, value: String) {
+        let existing = self.lines.iter_mut().find_map(|line| match line {
+            GroupLine::Entry { key: k, locale: l, value: v, raw } if k == key && l.as_deref() == locale => Some((v, raw)),
+            _ => None
+        });
+
+        match existing {
+            Some((v, raw)) => {
+                if *v != value {
+                    *v = value;
+                    *raw = None;
+                }
+            },
+            None => {
+                // Translations go right after the last line of the same key, new keys after the last entry
+                let position = self.lines.iter()
+                    .rposition(|line| matches!(line, GroupLine::Entry { key: k, .. } if k == key))
+                    .or_else(|| self.lines.iter().rposition(|line| matches!(line, GroupLine::Entry { .. })))
+                    .map(|i| i + 1)
+                    .unwrap_or(self.lines.len());
+
+                self.lines.insert(position, GroupLine::Entry {
+                    key: key.to_string(),
+                    locale: locale.map(String::from),
+                    value,
+                    raw: None,
+                });
+            }
+        }
+    }
+
+    /// Sets the raw value of an unlocalized key, which must already be escaped.
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.set_value(key, None, value.to_string());
+    }
+
+    /// Sets an unlocalized string key, escaping it as needed.
+    pub fn set_string(&mut self, key: &str, value: &str) {
+        self.set_value(key, None, escape(value));
+    }
+
+    /// Sets the translation of a localized string key for `locale`.
+    pub fn set_localized(&mut self, key: &str, locale: &str, value: &str) {
+        self.set_value(key, Some(locale), escape(value));
+    }
+
+    /// Sets a boolean key.
+    pub fn set_bool(&mut self, key: &str, value: bool) {
+        self.set_value(key, None, value.to_string());
+    }
+
+    /// Sets a semicolon-separated list key.
+    pub fn set_strings<S: AsRef<str>>(&mut self, key: &str, values: &[S]) {
+        let value: String = values.iter()
+            .map(|v| format!("{};", escape(v.as_ref()).replace(';', "\\;")))
+            .collect();
+
+        self.set_value(key, None, value);
+    }
+
+    /// Removes a key along with all of its translations.
+    pub fn remove(&mut self, key: &str) {
+        self.lines.retain(|line| !matches!(line, GroupLine::Entry { key: k, .. } if k == key));
+    }
+
+    /// Removes only the translations of a key.
+    pub fn remove_localized(&mut self, key: &str) {
+        self.lines.retain(|line| !matches!(line, GroupLine::Entry { key: k, locale: Some(_), .. } if k == key));
+    }
+}
+
+/// Escapes a string value for writing.
+pub fn escape(value: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in value.chars().enumerate() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            // Leading spaces would otherwise be trimmed by readers
+            ' ' if i == 0 => out.push_str("\\s"),
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+/// Resolves the escapes of a raw string value.
+pub fn unescape(value: &str) -> String {
+    let mut out = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => out.push(' '),
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('\\') => out.push('\\'),
+            Some(c) => {
+                out.push('\\');
+                out.push(c);
+            },
+            None => out.push('\\')
+        }
+    }
+
+    out
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(';') => current.push(';'),
+                Some(c) => {
+                    current.push('\\');
+                    current.push(c);
+                },
+                None => current.push('\\')
+            },
+            ';' => items.push(unescape(&std::mem::take(&mut current))),
+            c => current.push(c)
+        }
+    }
+    if !current.is_empty() {
+        items.push(unescape(&current));
+    }
+
+    items
+}
+
+/// Splits an unescaped `Exec` value into its arguments.
+///
+/// Field codes such as `%U` are dropped, since they only make sense when a file manager launches the entry.
+pub fn split_exec(exec: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut has_arg = false;
+    let mut chars = exec.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                quoted = !quoted;
+                has_arg = true;
+            },
+            '\\' if quoted => {
+                if let Some(c) = chars.next() {
+                    current.push(c);
+                }
+            },
+            c if c.is_whitespace() && !quoted => {
+                if has_arg {
+                    args.push(std::mem::take(&mut current));
+                    has_arg = false;
+                }
+            },
+            c => {
+                current.push(c);
+                has_arg = true;
+            }
+        }
+    }
+    if has_arg {
+        args.push(current);
+    }
+
+    args.into_iter()
+        .filter(|a| !(a.len() == 2 && a.starts_with('%') && a != "%%"))
+        .map(|a| a.replace("%%", "%"))
+        .collect()
+}
+
+/// Quotes an argument for an `Exec` value, if it needs it.
+///
+/// The result still has to go through `escape` when it is written, which `Group::set_string` does.
+pub fn quote_exec_arg(arg: &str) -> String {
+    const RESERVED: &[char] = &[' ', '\t', '\n', '"', '\'', '\\', '>', '<', '~', '|', '&', ';', '$', '*', '?', '#', '(', ')', '`'];
+
+    let arg = arg.replace('%', "%%");
+    if !arg.is_empty() && !arg.contains(RESERVED) {
+        return arg;
+    }
+
+    let mut out = String::from('"');
+    for c in arg.chars() {
+        if matches!(c, '"' | '`' | '$' | '\\') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out.push('"');
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIREFOX: &str = "# Written by hand\n\n[Desktop Entry]\nType=Application\nName=Firefox\nName[de]=Firefox Browser\nExec=firefox %u\n  # indented comment\nIcon = firefox\nActions=new-window;\n\n [Desktop Action new-window] \nName=New Window\nExec=firefox --new-window %u";
+
+    #[test]
+    fn round_trips_unchanged_files_exactly() {
+        let entry = DesktopEntry::parse(FIREFOX).unwrap();
+        assert_eq!(entry.to_string(), FIREFOX);
+
+        let with_newline = format!("{}\n", FIREFOX);
+        assert_eq!(DesktopEntry::parse(&with_newline).unwrap().to_string(), with_newline);
+    }
+
+    #[test]
+    fn rewrites_only_modified_lines() {
+        let mut entry = DesktopEntry::parse(FIREFOX).unwrap();
+        entry.desktop_entry_mut().set_string("Name", "Firefox");
+        entry.desktop_entry_mut().set_string("Comment", "Browse the web");
+        entry.desktop_entry_mut().set_localized("Name", "fr", "Navigateur");
+        entry.desktop_entry_mut().set_string("Icon", "/opt/firefox.png");
+
+        let written = entry.to_string();
+        assert!(written.contains("Name=Firefox\nName[de]=Firefox Browser\nName[fr]=Navigateur\n"));
+        assert!(written.contains("Icon=/opt/firefox.png\nActions=new-window;\nComment=Browse the web\n"));
+        assert!(written.contains("\n [Desktop Action new-window] \n"));
+        assert_eq!(DesktopEntry::parse(&written).unwrap().to_string(), written);
+    }
+
+    #[test]
+    fn reads_values() {
+        let entry = DesktopEntry::parse(FIREFOX).unwrap();
+        let group = entry.desktop_entry().unwrap();
+
+        assert_eq!(entry.icon().as_deref(), Some("firefox"));
+        assert_eq!(entry.actions(), ["new-window"]);
+        assert_eq!(entry.action("new-window").unwrap().get("Exec"), Some("firefox --new-window %u"));
+        assert_eq!(group.get_localized("Name", "de_DE.UTF-8@euro").as_deref(), Some("Firefox Browser"));
+        assert_eq!(group.get_localized("Name", "fr_FR").as_deref(), Some("Firefox"));
+    }
+
+    #[test]
+    fn rejects_duplicates() {
+        let error = DesktopEntry::parse("[Desktop Entry]\nName=A\nName[de]=B\nName=C\n").unwrap_err();
+        assert!(matches!(error.kind, DesktopParseErrorKind::DuplicateKey(ref k) if k == "Name"));
+        assert_eq!(error.line, 4);
+
+        let error = DesktopEntry::parse("[Desktop Entry]\nName[de]=A\nName[de] = B\n").unwrap_err();
+        assert!(matches!(error.kind, DesktopParseErrorKind::DuplicateKey(ref k) if k == "Name[de]"));
+
+        let error = DesktopEntry::parse("[A]\n[B]\n[A]\n").unwrap_err();
+        assert!(matches!(error.kind, DesktopParseErrorKind::DuplicateGroup(ref g) if g == "A"));
+
+        assert!(DesktopEntry::parse("[A]\nName=1\n[B]\nName=2\n").is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        assert!(matches!(DesktopEntry::parse("Name=A\n").unwrap_err().kind, DesktopParseErrorKind::EntryOutsideGroup));
+        assert!(matches!(DesktopEntry::parse("[A]\nnot an entry\n").unwrap_err().kind, DesktopParseErrorKind::InvalidLine));
+        assert!(matches!(DesktopEntry::parse("[A]\nName[de=A\n").unwrap_err().kind, DesktopParseErrorKind::InvalidLine));
+    }
+
+    #[test]
+    fn escapes_string_values() {
+        let value = " two\nlines\twith \\ backslash";
+        assert_eq!(escape(value), "\\stwo\\nlines\\twith \\\\ backslash");
+        assert_eq!(unescape(&escape(value)), value);
+
+        let mut group = Group::new("A");
+        group.set_strings("Keywords", &["a;b", "c"]);
+        assert_eq!(group.get("Keywords"), Some(r"a\;b;c;"));
+        assert_eq!(group.get_strings("Keywords").unwrap(), ["a;b", "c"]);
+    }
+
+    #[test]
+    fn splits_exec() {
+        assert_eq!(split_exec("firefox %u"), ["firefox"]);
+        assert_eq!(split_exec("  env  A=1   \"/opt/My Game/run\" --x "), ["env", "A=1", "/opt/My Game/run", "--x"]);
+        assert_eq!(split_exec(r#""a \"quoted\" \\ \$ \` arg""#), [r#"a "quoted" \ $ ` arg"#]);
+        assert_eq!(split_exec("echo 100%% \"\""), ["echo", "100%", ""]);
+        assert_eq!(split_exec("app %f %F %i --flag"), ["app", "--flag"]);
+    }
+
+    #[test]
+    fn quoted_args_split_back() {
+        let args = ["plain", "with space", "100%", "", "$HOME", "a \"b\" \\c `d`", "semi;colon"];
+        let exec = args.iter().map(|a| quote_exec_arg(a)).collect::<Vec<_>>().join(" ");
+
+        assert_eq!(quote_exec_arg("plain"), "plain");
+        assert_eq!(quote_exec_arg("100%"), "100%%");
+        assert_eq!(split_exec(&exec), args);
+
+        // Writing an escaped `Exec` key and reading it back must not change the arguments
+        let mut group = Group::new(DESKTOP_ENTRY_GROUP);
+        group.set_string("Exec", &exec);
+        assert_eq!(split_exec(&group.get_string("Exec").unwrap()), args);
+    }
+}
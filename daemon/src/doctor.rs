@@ -0,0 +1,148 @@
+//! Checks of what launchers depend on outside of the daemon, run with `--doctor`.
+
+use std::{env::VarError, fmt};
+
+use crate::{config::{Config, LaunchPreset}, steam::{self, SteamRoot}, url_handler::{self, HandlerRecord, HandlerStatus, XdgDirs}};
+
+/// The outcome of one check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+    /// What was checked.
+    pub name: &'static str,
+    /// Whether everything is in order.
+    pub passed: bool,
+    /// What was found, or what is wrong.
+    pub detail: String,
+}
+
+impl fmt::Display for Check {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{}] {}: {}", if self.passed { "ok" } else { "!!" }, self.name, self.detail)
+    }
+}
+
+/// Runs every check for `config`.
+pub fn run(config: &Config) -> Vec<Check> {
+    let roots = steam::discover_steam_roots().unwrap_or_default();
+    let registered = HandlerRecord::load().map(|r| !r.is_empty()).unwrap_or(false);
+
+    check(config, &roots, XdgDirs::from_env(), registered)
+}
+
+// `registered` tells whether the daemon registered a `steam://` handler that wasn't undone yet
+fn check(config: &Config, roots: &[SteamRoot], dirs: Result<XdgDirs, VarError>, registered: bool) -> Vec<Check> {
+    let mut checks = Vec::new();
+
+    checks.push(Check {
+        name: "Steam installations",
+        passed: !roots.is_empty(),
+        detail: match roots.is_empty() {
+            true => "none found".to_string(),
+            false => roots.iter().map(|r| format!("{:?} at {}", r.kind, r.install_dir.display())).collect::<Vec<_>>().join(", ")
+        }
+    });
+
+    // Custom templates can run anything, so only the presets are known to need a particular Steam
+    if config.exec_template.is_none() && config.launch != LaunchPreset::XdgOpen {
+        let kinds: Vec<_> = match config.launch.kind() {
+            Some(kind) => vec![kind],
+            None => roots.iter().map(|r| r.kind).collect()
+        };
+        let missing: Vec<&str> = kinds.iter().filter(|k| !url_handler::can_run(**k)).map(|k| k.steam_command()).collect();
+
+        checks.push(Check {
+            name: "Launch command",
+            passed: missing.is_empty(),
+            detail: match missing.is_empty() {
+                true => "every launcher's Steam can be run".to_string(),
+                false => format!("{} can't be run", missing.join(", "))
+            }
+        });
+    }
+
+    let handler = match dirs {
+        Ok(dirs) => {
+            let status = url_handler::status(&dirs);
+            let detail = match &status {
+                HandlerStatus::Working { id, .. } if registered && id == url_handler::HANDLER_ID => {
+                    format!("{}, registered by the daemon and undone with --unregister-url-handler", status)
+                },
+                HandlerStatus::Working { .. } => status.to_string(),
+                _ if !config.uses_url_handler() => format!("{}, which launchers don't need since they run Steam directly", status),
+                _ if config.register_url_handler => format!("{}, which the next synchronization fixes", status),
+                _ => format!("{}, and register_url_handler is disabled", status)
+            };
+            Check { name: "steam:// handler", passed: status.is_working() || !config.uses_url_handler(), detail }
+        },
+        Err(e) => Check { name: "steam:// handler", passed: false, detail: format!("unable to find XDG directories: {}", e) }
+    };
+    checks.push(handler);
+
+    checks
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, path::PathBuf, process};
+
+    use crate::steam::SteamInstallKind;
+
+    use super::*;
+
+    fn dirs(root: &std::path::Path) -> XdgDirs {
+        XdgDirs { config_home: root.join("config"), config_dirs: Vec::new(), data_home: root.join("data"), data_dirs: Vec::new(), desktops: Vec::new() }
+    }
+
+    fn find<'a>(checks: &'a [Check], name: &str) -> Option<&'a Check> {
+        checks.iter().find(|c| c.name == name)
+    }
+
+    #[test]
+    fn checks_steam_and_the_launch_command() {
+        let root = env::temp_dir().join(format!("steam-shortcut-sync-doctor-steam-{}", process::id()));
+        let flatpak = [SteamRoot { kind: SteamInstallKind::Flatpak, install_dir: PathBuf::from("/steam"), data_dir: PathBuf::from("/data") }];
+
+        let checks = check(&Config::default(), &[], Ok(dirs(&root)), false);
+        assert_eq!(find(&checks, "Steam installations").map(|c| (c.passed, c.detail.as_str())), Some((false, "none found")));
+        // Without installations there is no Steam a launcher could need
+        assert!(find(&checks, "Launch command").unwrap().passed);
+
+        let checks = check(&Config::default(), &flatpak, Ok(dirs(&root)), false);
+        assert_eq!(find(&checks, "Steam installations").map(|c| (c.passed, c.detail.as_str())), Some((true, "Flatpak at /steam")));
+        assert_eq!(find(&checks, "Launch command").unwrap().passed, url_handler::can_run(SteamInstallKind::Flatpak));
+
+        // Other launchers can run anything
+        for config in ["launch = \"xdg-open\"", "exec_template = \"run {id}\""] {
+            let checks = check(&Config::parse(config).unwrap(), &flatpak, Ok(dirs(&root)), false);
+            assert!(find(&checks, "Launch command").is_none());
+        }
+    }
+
+    #[test]
+    fn checks_the_url_handler_only_when_launchers_need_it() {
+        let root = env::temp_dir().join(format!("steam-shortcut-sync-doctor-handler-{}", process::id()));
+        let _ = fs::remove_dir_all(&root);
+        let xdg_open = Config::parse("launch = \"xdg-open\"").unwrap();
+        let handler = |config: &Config, dirs, registered| check(config, &[], dirs, registered).pop().unwrap();
+
+        let missing = handler(&xdg_open, Ok(dirs(&root)), false);
+        assert!(!missing.passed);
+        assert!(missing.detail.ends_with("which the next synchronization fixes"));
+        let disabled = Config { register_url_handler: false, ..xdg_open.clone() };
+        assert!(handler(&disabled, Ok(dirs(&root)), false).detail.ends_with("register_url_handler is disabled"));
+        assert!(handler(&Config::default(), Ok(dirs(&root)), false).passed);
+        assert!(!handler(&xdg_open, Err(VarError::NotPresent), false).passed);
+
+        // The daemon's own handler says how to undo it
+        let program = root.join("steam");
+        fs::create_dir_all(root.join("data/applications")).unwrap();
+        fs::write(&program, "").unwrap();
+        fs::write(root.join("data/applications").join(url_handler::HANDLER_ID), format!("[Desktop Entry]\nType=Application\nName=Steam\nExec={} %u\nMimeType={};\n", program.display(), url_handler::SCHEME_MIME_TYPE)).unwrap();
+        let registered = handler(&xdg_open, Ok(dirs(&root)), true);
+        assert!(registered.passed);
+        assert!(registered.detail.ends_with("undone with --unregister-url-handler"));
+        assert!(!handler(&xdg_open, Ok(dirs(&root)), false).detail.contains("--unregister-url-handler"));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+//! Turns `.desktop` files into non-Steam shortcuts so they show up in Steam and Big Picture.
+//!
+//! A `.desktop` file is exported if it is inside the export directory or if its `[Desktop Entry]` group sets `X-SteamShortcutSync-Export=true`.
+
+use std::{env, fs, path::{Path, PathBuf}};
+
+use walkdir::WalkDir;
+
+use crate::{desktop::{self, DesktopEntry}, shortcuts::NonSteamShortcut};
+
+/// The key that marks a `.desktop` file for export outside of the export directory.
+pub const EXPORT_KEY: &str = "X-SteamShortcutSync-Export";
+
+/// Collects the shortcuts for every exported `.desktop` file.
+///
+/// Every file in `export_dir` is exported, while files in `applications_dir` are only exported if they are tagged with `EXPORT_KEY`.
+/// Entries that are `Hidden` or `NoDisplay` are skipped, as are entries that launch through `steam://`, since they are already Steam games.
+pub fn exported_shortcuts(export_dir: Option<&Path>, applications_dir: &Path) -> Vec<NonSteamShortcut> {
+    let mut shortcuts = Vec::new();
+
+    let sources = export_dir.map(|d| (d, false)).into_iter().chain([(applications_dir, true)]);
+    for (dir, needs_tag) in sources {
+        for entry in WalkDir::new(dir).into_iter().filter_map(|f| f.ok()) {
+            if entry.path().extension().map(|e| e != "desktop").unwrap_or(true) {
+                continue;
+            }
+
+            let contents = match fs::read_to_string(entry.path()) {
+                Ok(c) => c,
+                Err(_) => continue
+            };
+
+            if let Some(shortcut) = shortcut_from_desktop(&contents, needs_tag) {
+                if !shortcuts.iter().any(|s: &NonSteamShortcut| s.appid == shortcut.appid) {
+                    shortcuts.push(shortcut);
+                }
+            }
+        }
+    }
+
+    shortcuts
+}
+
+fn shortcut_from_desktop(contents: &str, needs_tag: bool) -> Option<NonSteamShortcut> {
+    let entry = DesktopEntry::parse(contents).ok()?;
+    let group = entry.desktop_entry()?;
+
+    if entry.hidden() || entry.no_display() || (needs_tag && group.get_bool(EXPORT_KEY) != Some(true)) {
+        return None;
+    }
+
+    let name = entry.name()?;
+    let exec = entry.exec()?;
+    if exec.contains("steam://") {
+        return None;
+    }
+
+    let mut args = desktop::split_exec(&exec);
+    if args.is_empty() {
+        return None;
+    }
+    let program = resolve_program(&args.remove(0));
+
+    let start_dir = match group.get_string("Path") {
+        Some(p) if !p.is_empty() => PathBuf::from(p),
+        _ => program.parent().map(Path::to_path_buf).unwrap_or_default()
+    };
+
+    // Steam only understands image paths, not icon theme names
+    let icon = entry.icon().filter(|i| i.starts_with('/')).unwrap_or_default();
+
+    let launch_options = args.iter()
+        .map(|a| if a.contains(char::is_whitespace) { format!("\"{}\"", a) } else { a.clone() })
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    Some(NonSteamShortcut::new(
+        name,
+        format!("\"{}\"", program.display()),
+        format!("\"{}\"", start_dir.display()),
+        icon,
+        launch_options
+    ))
+}
+
+// Steam needs an absolute path to start a program
+fn resolve_program(program: &str) -> PathBuf {
+    if program.contains('/') {
+        return PathBuf::from(program);
+    }
+
+    env::var_os("PATH")
+        .and_then(|paths| env::split_paths(&paths).map(|p| p.join(program)).find(|p| p.is_file()))
+        .unwrap_or_else(|| PathBuf::from(program))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use crate::shortcuts::shortcut_appid;
+
+    use super::*;
+
+    fn desktop(exec: &str, extra: &str) -> String {
+        format!("[Desktop Entry]\nType=Application\nName=Game\nExec={}\n{}", exec, extra)
+    }
+
+    #[test]
+    fn splits_exec_into_the_program_and_launch_options() {
+        let shortcut = shortcut_from_desktop(&desktop(r#""/opt/My Game/game" --fullscreen "save slot" %U"#, "Icon=/opt/My Game/icon.png\n"), false).unwrap();
+        assert_eq!(shortcut.app_name, "Game");
+        assert_eq!(shortcut.exe, "\"/opt/My Game/game\"");
+        assert_eq!(shortcut.start_dir, "\"/opt/My Game\"");
+        assert_eq!(shortcut.launch_options, "--fullscreen \"save slot\"");
+        assert_eq!(shortcut.icon, "/opt/My Game/icon.png");
+
+        let shortcut = shortcut_from_desktop(&desktop("/usr/bin/game", "Path=/srv/game\nIcon=game\n"), false).unwrap();
+        assert_eq!(shortcut.start_dir, "\"/srv/game\"");
+        assert_eq!(shortcut.launch_options, "");
+        // Steam can't resolve theme icon names
+        assert_eq!(shortcut.icon, "");
+    }
+
+    #[test]
+    fn derives_a_stable_app_id() {
+        let first = shortcut_from_desktop(&desktop("/usr/bin/game --windowed", ""), false).unwrap();
+        let second = shortcut_from_desktop(&desktop("/usr/bin/game --fullscreen", "Comment=Edited\n"), false).unwrap();
+
+        // The id only depends on the program and the name, so editing the arguments keeps the shortcut's library data
+        assert_eq!(first.appid, shortcut_appid("\"/usr/bin/game\"", "Game"));
+        assert_eq!(first.appid, second.appid);
+    }
+
+    #[test]
+    fn skips_hidden_untagged_and_steam_entries() {
+        assert!(shortcut_from_desktop(&desktop("/usr/bin/game", "Hidden=true\n"), false).is_none());
+        assert!(shortcut_from_desktop(&desktop("/usr/bin/game", "NoDisplay=true\n"), false).is_none());
+        assert!(shortcut_from_desktop(&desktop("/usr/bin/game", ""), true).is_none());
+        assert!(shortcut_from_desktop(&desktop("/usr/bin/game", &format!("{}=true\n", EXPORT_KEY)), true).is_some());
+
+        // The daemon's own launchers
+        assert!(shortcut_from_desktop(&desktop("steam steam://rungameid/620", &format!("{}=true\n", EXPORT_KEY)), true).is_none());
+        assert!(shortcut_from_desktop(&desktop("xdg-open steam://rungameid/620", ""), false).is_none());
+    }
+
+    #[test]
+    fn collects_exported_files_once() {
+        let dir = env::temp_dir().join(format!("steam-shortcut-sync-export-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let export_dir = dir.join("export");
+        let applications = dir.join("applications");
+        fs::create_dir_all(export_dir.join("nested")).unwrap();
+        fs::create_dir_all(&applications).unwrap();
+
+        fs::write(export_dir.join("nested/game.desktop"), desktop("/usr/bin/game", "")).unwrap();
+        fs::write(export_dir.join("notes.txt"), desktop("/usr/bin/notes", "")).unwrap();
+        fs::write(applications.join("copy.desktop"), desktop("/usr/bin/game", &format!("{}=true\n", EXPORT_KEY))).unwrap();
+        fs::write(applications.join("tagged.desktop"), desktop("/usr/bin/tagged", &format!("{}=true\n", EXPORT_KEY))).unwrap();
+        fs::write(applications.join("untagged.desktop"), desktop("/usr/bin/untagged", "")).unwrap();
+
+        let exes = exported_shortcuts(Some(&export_dir), &applications).into_iter().map(|s| s.exe).collect::<Vec<_>>();
+        assert_eq!(exes, ["\"/usr/bin/game\"", "\"/usr/bin/tagged\""]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,220 @@
+//! Rules deciding which apps get launchers.
+//!
+//! Rules are checked in order and the first one that matches an app decides whether it gets a launcher.
+//! Apps that no rule matches get one, so a final rule without criteria turns the rules above it into an allowlist:
+//!
+//! ```toml
+//! [[filters]]
+//! action = "deny"
+//! name = "^(Proton|Steam Linux Runtime)"
+//!
+//! [[filters]]
+//! action = "deny"
+//! types = ["tool", "demo"]
+//!
+//! [[filters]]
+//! action = "allow"
+//! library = "/mnt/games"
+//!
+//! [[filters]]
+//! action = "allow"
+//! ids = ["1493710", "12501462953196781568"]
+//!
+//! [[filters]]
+//! action = "deny"
+//! ```
+
+use std::{fmt, path::{Path, PathBuf}};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::appinfo::AppType;
+
+/// What happens to the apps a rule matches.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FilterAction {
+    /// The app gets a launcher.
+    Allow,
+    /// The app doesn't get a launcher, and one it already has is removed.
+    Deny,
+}
+
+/// A rule matching apps by every criterion it sets.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FilterRule {
+    /// What happens to matching apps.
+    pub action: FilterAction,
+    /// App ids, or game ids for non-Steam shortcuts, of which one must match.
+    /// They are strings because game ids don't fit into TOML's integers.
+    #[serde(default)]
+    pub ids: Vec<String>,
+    /// A regular expression the name from Steam must match.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// App types from `appinfo.vdf`, such as `game`, `tool` or `demo`, of which one must match.
+    /// Non-Steam shortcuts and apps Steam has no info on don't have a type, so they never match.
+    #[serde(default)]
+    pub types: Vec<String>,
+    /// A directory the app's library folder must be in. Non-Steam shortcuts never match.
+    #[serde(default)]
+    pub library: Option<PathBuf>,
+}
+
+/// Everything about an app that rules can match.
+#[derive(Debug, Clone, Copy)]
+pub struct FilterSubject<'a> {
+    /// The app id, or game id for non-Steam shortcuts.
+    pub id: &'a str,
+    /// The name from Steam.
+    pub name: &'a str,
+    /// The type from `appinfo.vdf`, if it is known.
+    pub app_type: Option<&'a AppType>,
+    /// The library folder it is installed in, if it is known.
+    pub library: Option<&'a Path>,
+}
+
+/// A list of rules ready to be matched.
+#[derive(Debug, Clone)]
+pub struct Filter<'a> {
+    rules: Vec<(&'a FilterRule, Option<Regex>)>,
+}
+
+impl<'a> Filter<'a> {
+    /// Compiles the name patterns of `rules`.
+    pub fn new(rules: &'a [FilterRule]) -> Result<Filter<'a>, regex::Error> {
+        let rules = rules.iter()
+            .map(|rule| Ok((rule, rule.name.as_deref().map(Regex::new).transpose()?)))
+            .collect::<Result<Vec<_>, regex::Error>>()?;
+
+        Ok(Filter { rules })
+    }
+
+    /// Returns which rule denies `subject` a launcher, if one does.
+    pub fn excludes(&self, subject: &FilterSubject) -> Option<String> {
+        let (index, (rule, _)) = self.rules.iter()
+            .enumerate()
+            .find(|(_, (rule, name))| rule.matches(subject, name.as_ref()))?;
+
+        match rule.action {
+            FilterAction::Allow => None,
+            FilterAction::Deny => Some(format!("filter rule {} ({})", index + 1, rule))
+        }
+    }
+}
+
+impl FilterRule {
+    fn matches(&self, subject: &FilterSubject, name: Option<&Regex>) -> bool {
+        let matches_id = || subject.id.parse::<u64>().map(|id| self.ids.iter().any(|i| i.parse() == Ok(id))).unwrap_or(false);
+        if !self.ids.is_empty() && !matches_id() {
+            return false;
+        }
+
+        if name.map(|n| !n.is_match(subject.name)).unwrap_or(false) {
+            return false;
+        }
+
+        if !self.types.is_empty() {
+            let app_type = match subject.app_type {
+                Some(t) => t.to_string(),
+                None => return false
+            };
+            if !self.types.iter().any(|t| t.eq_ignore_ascii_case(&app_type)) {
+                return false;
+            }
+        }
+
+        match (&self.library, subject.library) {
+            (Some(dir), Some(library)) => library.starts_with(dir),
+            (Some(_), None) => false,
+            (None, _) => true
+        }
+    }
+}
+
+impl fmt::Display for FilterRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            FilterAction::Allow => write!(f, "allow")?,
+            FilterAction::Deny => write!(f, "deny")?,
+        }
+
+        let mut criteria = Vec::new();
+        if !self.ids.is_empty() {
+            criteria.push(format!("ids {}", self.ids.join(", ")));
+        }
+        if let Some(name) = &self.name {
+            criteria.push(format!("name {:?}", name));
+        }
+        if !self.types.is_empty() {
+            criteria.push(format!("types {}", self.types.join(", ")));
+        }
+        if let Some(library) = &self.library {
+            criteria.push(format!("library {}", library.display()));
+        }
+
+        if criteria.is_empty() {
+            write!(f, " everything")
+        } else {
+            write!(f, " {}", criteria.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Deserialize)]
+    struct Rules {
+        filters: Vec<FilterRule>,
+    }
+
+    fn rules(toml: &str) -> Vec<FilterRule> {
+        toml::from_str::<Rules>(toml).unwrap().filters
+    }
+
+    fn subject<'a>(id: &'a str, name: &'a str, app_type: Option<&'a AppType>, library: Option<&'a Path>) -> FilterSubject<'a> {
+        FilterSubject { id, name, app_type, library }
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let rules = rules(r#"
+            [[filters]]
+            action = "allow"
+            ids = ["12501462953196781568", "0010"]
+
+            [[filters]]
+            action = "deny"
+            name = "^Proton"
+
+            [[filters]]
+            action = "deny"
+            types = ["Tool", "demo"]
+
+            [[filters]]
+            action = "deny"
+            library = "/mnt/slow"
+        "#);
+        let filter = Filter::new(&rules).unwrap();
+        let tool = AppType::Tool;
+        let game = AppType::Game;
+
+        assert_eq!(filter.excludes(&subject("12501462953196781568", "Proton Shortcut", None, None)), None);
+        assert_eq!(filter.excludes(&subject("10", "Proton 8.0", Some(&tool), None)), None);
+        assert_eq!(filter.excludes(&subject("20", "Proton 8.0", Some(&game), None)).as_deref(), Some(r#"filter rule 2 (deny name "^Proton")"#));
+        assert_eq!(filter.excludes(&subject("30", "Runtime", Some(&tool), None)).as_deref(), Some("filter rule 3 (deny types Tool, demo)"));
+        assert_eq!(filter.excludes(&subject("40", "Game", Some(&game), Some(Path::new("/mnt/slow/steam")))).as_deref(), Some("filter rule 4 (deny library /mnt/slow)"));
+        assert_eq!(filter.excludes(&subject("50", "Game", None, Some(Path::new("/mnt/slower")))), None);
+    }
+
+    #[test]
+    fn rules_without_criteria_match_everything() {
+        let rules = rules("[[filters]]\naction = \"deny\"");
+
+        assert_eq!(Filter::new(&rules).unwrap().excludes(&subject("1", "Any", None, None)).as_deref(), Some("filter rule 1 (deny everything)"));
+    }
+}
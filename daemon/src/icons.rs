@@ -0,0 +1,160 @@
+//! Turning Steam's artwork into launcher icons.
+//!
+//! Many games don't get a `steam_icon_<id>.png` in Steam's icon theme, so other artwork is used instead.
+//! Any image this module can decode is padded to a square and resized to the standard icon theme sizes.
+
+use std::{fmt, fs, io::{self, Cursor}, path::{Path, PathBuf}};
+
+use image::{imageops::{self, FilterType}, ImageFormat, RgbaImage};
+
+use crate::shortcuts::{self, NonSteamShortcut};
+
+/// The sizes icons are rendered at, all of which the hicolor theme defines.
+pub const ICON_SIZES: [u32; 8] = [16, 24, 32, 48, 64, 96, 128, 256];
+
+/// An icon rendered at one size.
+#[derive(Debug, Clone)]
+pub struct RenderedIcon {
+    /// The width and height in pixels.
+    pub size: u32,
+    /// The encoded PNG.
+    pub png: Vec<u8>,
+}
+
+/// Possible errors when rendering an icon.
+#[derive(Debug)]
+pub struct IconError {
+    /// The specific kind of error.
+    pub kind: IconErrorKind
+}
+
+#[derive(Debug)]
+pub enum IconErrorKind {
+    /// The image could not be read.
+    Io(io::Error),
+    /// The image is in a format that isn't supported or is corrupt.
+    Decode(String),
+}
+
+impl fmt::Display for IconError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            IconErrorKind::Io(e) => write!(f, "{}", e),
+            IconErrorKind::Decode(e) => write!(f, "unable to decode image: {}", e),
+        }
+    }
+}
+
+/// Renders the image at `path` at every size in `ICON_SIZES` up to its own size.
+///
+/// Images that aren't square are centered on a transparent background, so wide art such as headers isn't stretched.
+/// Images smaller than the smallest size are rendered at that size only.
+pub fn render_icon(path: &Path) -> Result<Vec<RenderedIcon>, IconError> {
+    let data = fs::read(path).map_err(|e| IconError { kind: IconErrorKind::Io(e) })?;
+    let image = image::load_from_memory(&data)
+        .map_err(|e| IconError { kind: IconErrorKind::Decode(e.to_string()) })?
+        .to_rgba8();
+
+    let side = image.width().max(image.height());
+    let mut square = RgbaImage::new(side, side);
+    imageops::overlay(&mut square, &image, ((side - image.width()) / 2) as i64, ((side - image.height()) / 2) as i64);
+
+    let mut sizes: Vec<u32> = ICON_SIZES.iter().copied().filter(|&s| s <= side).collect();
+    if sizes.is_empty() {
+        sizes.push(ICON_SIZES[0]);
+    }
+
+    sizes.into_iter().map(|size| {
+        let resized = imageops::resize(&square, size, size, FilterType::Lanczos3);
+        let mut png = Vec::new();
+        resized.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+            .map_err(|e| IconError { kind: IconErrorKind::Decode(e.to_string()) })?;
+
+        Ok(RenderedIcon { size, png })
+    }).collect()
+}
+
+/// The artwork of a Steam app to fall back to when Steam didn't install an icon for it, best first.
+///
+/// This is the client icon, then the logo and then the header from `appcache/librarycache`.
+/// Older clients keep these as `<id>_icon.jpg`, `<id>_logo.png` and `<id>_header.jpg`.
+/// Newer ones use a directory per app with `logo.png` and `header.jpg`, where the client icon is named after its SHA-1 hash, as in `<id>/<hash>.jpg`.
+pub fn app_artwork(install_dir: &Path, appid: &str) -> Vec<PathBuf> {
+    let cache = install_dir.join("appcache/librarycache");
+    let app_dir = cache.join(appid);
+
+    let mut client_icons: Vec<PathBuf> = fs::read_dir(&app_dir).into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| is_client_icon(p))
+        .collect();
+    client_icons.sort();
+
+    let mut artwork = vec![cache.join(format!("{}_icon.jpg", appid))];
+    artwork.extend(client_icons);
+    artwork.extend([
+        cache.join(format!("{}_logo.png", appid)),
+        app_dir.join("logo.png"),
+        cache.join(format!("{}_header.jpg", appid)),
+        app_dir.join("header.jpg"),
+    ]);
+
+    artwork.into_iter().filter(|p| p.is_file()).collect()
+}
+
+fn is_client_icon(path: &Path) -> bool {
+    let is_hash = path.file_stem()
+        .and_then(|s| s.to_str())
+        .map(|s| s.len() == 40 && s.chars().all(|c| c.is_ascii_hexdigit()))
+        .unwrap_or(false);
+
+    is_hash && path.extension().map(|e| e == "jpg").unwrap_or(false)
+}
+
+/// The artwork of a non-Steam shortcut, best first.
+///
+/// This is the image set as its icon, then a custom icon from the `grid` directory of any user.
+pub fn shortcut_artwork(install_dir: &Path, shortcut: &NonSteamShortcut) -> Vec<PathBuf> {
+    let mut artwork: Vec<PathBuf> = shortcut.icon_path().filter(|p| p.is_file()).into_iter().collect();
+
+    for config in shortcuts::user_config_dirs(install_dir) {
+        for extension in ["png", "jpg"] {
+            let path = config.join("grid").join(format!("{}_icon.{}", shortcut.appid, extension));
+            if path.is_file() {
+                artwork.push(path);
+            }
+        }
+    }
+
+    artwork
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+
+    use super::*;
+
+    #[test]
+    fn finds_app_artwork_in_both_layouts() {
+        let dir = env::temp_dir().join(format!("steam-shortcut-sync-icons-{}", std::process::id()));
+        let cache = dir.join("appcache/librarycache");
+        fs::create_dir_all(cache.join("10")).unwrap();
+        let hash = "0123456789abcdef0123456789abcdef01234567";
+        for file in ["10_header.jpg", "10/logo.png", "10/header.jpg", "10/library_600x900.jpg", &format!("10/{}.jpg", hash), "20_icon.jpg"] {
+            fs::write(cache.join(file), b"").unwrap();
+        }
+
+        assert_eq!(app_artwork(&dir, "10"), [
+            cache.join(format!("10/{}.jpg", hash)),
+            cache.join("10/logo.png"),
+            cache.join("10_header.jpg"),
+            cache.join("10/header.jpg"),
+        ]);
+        assert_eq!(app_artwork(&dir, "20"), [cache.join("20_icon.jpg")]);
+        assert!(app_artwork(&dir, "30").is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,24 +1,72 @@
 //! # Steam Shortcut Sync
 //! 
-//! A library and daemon for automatically synchronizing Steam shortcuts from a Flatpak installation to the normal applications directory.
+//! A library and daemon for automatically synchronizing Steam shortcuts from native, Flatpak and Snap installations to the normal applications directory.
 
-use std::{sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, TryRecvError}, Arc}, thread, os::unix::net::UnixListener, env::{self, VarError}, path::{Path, PathBuf}, io::{self, Read, Error}, process::{self, Command}, time::Duration, fs, collections::HashMap};
+use std::{sync::{atomic::{AtomicBool, Ordering}, mpsc::{self, TryRecvError}, Arc, Mutex}, thread, os::unix::net::UnixListener, env::{self, VarError}, path::{Path, PathBuf}, io::{self, Read, Write}, process, fs, collections::{HashMap, HashSet}};
 use lazy_static::lazy_static;
+use serde::Deserialize;
 
-use notify::Watcher;
+use notify::{DebouncedEvent, RecommendedWatcher, Watcher};
 use regex::Regex;
 use walkdir::WalkDir;
 
+pub mod appinfo;
+pub mod binary_vdf;
+pub mod categories;
+pub mod config;
+pub mod desktop;
+pub mod doctor;
+pub mod export;
+pub mod filter;
+pub mod icons;
+pub mod library;
+pub mod plan;
+pub mod shortcuts;
+pub mod state;
+pub mod steam;
+pub mod url_handler;
+pub mod vdf;
+pub mod wm_class;
+
+use appinfo::AppType;
+use config::{Config, ExecTarget, SharedConfig};
+use desktop::DesktopEntry;
+use library::InstalledApp;
+use filter::{Filter, FilterSubject};
+use plan::{Destination, ExcludedEntry, ExistingEntry, SourceEntry, SourceIcon, SyncPlan};
+use shortcuts::{NonSteamShortcut, ShortcutWriteError, ShortcutWriteErrorKind};
+use state::{ArtifactKind, OwnedArtifact, Ownership, SyncState};
+use steam::{SteamInstallKind, SteamRoot};
+use url_handler::{HandlerRecord, XdgDirs};
+use wm_class::LearnedClasses;
+
 /// Coordinates and controls synchronization requests.
 pub struct Synchronizer {
     thread: Option<thread::JoinHandle<()>>
 }
 
+lazy_static! {
+    /// Held while the destination is read or written, so dry runs never see a synchronization halfway through.
+    static ref SYNC_LOCK: Mutex<()> = Mutex::new(());
+}
+
+/// The `[Desktop Entry]` key launchers are tagged with their app id by, since a custom `Exec` doesn't have to mention it in a recognizable way.
+pub const APP_ID_KEY: &str = "X-SteamShortcutSync-AppId";
+
 enum SynchronizerChildCommand {
-    Run,
+    Run(SyncRequest),
     Die
 }
 
+/// What a `Synchronizer` is asked to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncRequest {
+    /// Reconciles every launcher with Steam.
+    Full,
+    /// Only installs icons for existing launchers that don't have any yet, such as when Steam writes them late.
+    Icons,
+}
+
 /// Possible errors when creating a `Synchronizer`.
 pub struct SynchronizerCreationError {
     /// The specific kind of error.
@@ -33,20 +81,136 @@ pub enum SynchronizerCreationErrorKind {
     NoApplicationsDir,
 }
 
-#[derive(Hash, PartialEq, Eq)]
+/// Where a `Synchronizer` gets the games it creates launchers for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncSource {
+    /// Only games Steam wrote a desktop shortcut for.
+    Shortcuts,
+    /// Every fully installed game in every Steam library, whether or not Steam made a shortcut for it.
+    InstalledApps,
+}
+
+/// An entry of a launcher's context menu, opening part of Steam for its game.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum LauncherAction {
+    /// The game's page in the library.
+    Library,
+    /// The game's store page.
+    Store,
+    /// The game's community hub.
+    Community,
+    /// The screenshots taken in the game.
+    Screenshots,
+    /// The game's properties dialog.
+    Properties,
+    /// Asks Steam to uninstall the game.
+    Uninstall,
+}
+
+impl LauncherAction {
+    /// Every action, in the order they are listed in menus by default.
+    pub const ALL: [LauncherAction; 6] = [LauncherAction::Library, LauncherAction::Store, LauncherAction::Community, LauncherAction::Screenshots, LauncherAction::Properties, LauncherAction::Uninstall];
+
+    /// The id of the action in `Actions`.
+    pub fn id(&self) -> &'static str {
+        match self {
+            LauncherAction::Library => "library",
+            LauncherAction::Store => "store",
+            LauncherAction::Community => "community",
+            LauncherAction::Screenshots => "screenshots",
+            LauncherAction::Properties => "properties",
+            LauncherAction::Uninstall => "uninstall",
+        }
+    }
+
+    /// The name shown in menus.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LauncherAction::Library => "Open in Library",
+            LauncherAction::Store => "Store Page",
+            LauncherAction::Community => "Community Hub",
+            LauncherAction::Screenshots => "Screenshots",
+            LauncherAction::Properties => "Properties",
+            LauncherAction::Uninstall => "Uninstall",
+        }
+    }
+
+    /// The `steam://` URL that opens the action for the app `id`.
+    pub fn url(&self, id: &str) -> String {
+        match self {
+            LauncherAction::Library => format!("steam://nav/games/details/{}", id),
+            LauncherAction::Store => format!("steam://store/{}", id),
+            LauncherAction::Community => format!("steam://url/GameHub/{}", id),
+            LauncherAction::Screenshots => format!("steam://open/screenshots/{}", id),
+            LauncherAction::Properties => format!("steam://gameproperties/{}", id),
+            LauncherAction::Uninstall => format!("steam://uninstall/{}", id),
+        }
+    }
+}
+
 struct SteamDesktopFile {
     name: String,
     id: String,
+    /// Translated names by locale.
+    localized_names: Vec<(String, String)>,
+    /// The entry Steam wrote, if there is one, whose keys are carried over.
+    entry: Option<DesktopEntry>,
+    /// Where the file was loaded from, if it was loaded from disk.
+    path: Option<PathBuf>,
+    /// The kind of app, if Steam has info on it.
+    app_type: Option<AppType>,
+    /// The library folder the app is installed in, if it is known.
+    library: Option<PathBuf>,
+    /// Genre names from Steam.
+    genres: Vec<String>,
+    /// Store tag names from Steam, most relevant first.
+    tags: Vec<String>,
+    /// The kind of Steam installation the app was found in.
+    kind: SteamInstallKind,
+}
+
+impl SteamDesktopFile {
+    fn new(name: String, id: String) -> SteamDesktopFile {
+        SteamDesktopFile { name, id, localized_names: Vec::new(), entry: None, path: None, app_type: None, library: None, genres: Vec::new(), tags: Vec::new(), kind: SteamInstallKind::Native }
+    }
+
+    /// The name of the generated file, following the desktop file ID recommendations.
+    ///
+    /// App ids are numeric, so unlike display names they are always valid file names and never collide.
+    fn file_name(&self) -> String {
+        format!("steam-app-{}.desktop", self.id)
+    }
+
+    /// Returns `true` if `contents` is exactly what versions before the state file existed wrote for this file.
+    fn is_legacy(&self, contents: &str) -> bool {
+        contents == format!("[Desktop Entry]\nName={}\nIcon=steam_icon_{}\nType=Application\nExec=xdg-open steam://rungameid/{}", self.name, self.id, self.id)
+    }
+}
+
+/// Every `steam_icon_<id>.png` below a theme directory by app id, largest first with one icon for each size.
+type ThemeIcons = HashMap<String, Vec<(u32, u32, PathBuf)>>;
+
+/// Where the icons of a launcher come from.
+struct IconSource {
+    /// A hicolor theme directory that may contain a `steam_icon_<id>.png`.
+    theme: PathBuf,
+    /// Artwork an icon is made from if the theme has none, best first.
+    artwork: Vec<PathBuf>,
+    /// An image that is referenced directly if no icon can be made, as set on non-Steam shortcuts.
+    file: Option<PathBuf>,
 }
 
 impl Synchronizer {
     /// Creates a new `Synchronizer`.
     /// 
-    /// An event from `receiver` triggers a synchronization.
+    /// An event from `receiver` triggers a synchronization, or an icon fixup for `SyncRequest::Icons`.
     /// Setting `run` to `false` terminates `Synchronizer`'s internal thread, ceasing its function.
-    /// `sleep_time` controls how long the thread sleeps between checking for a synchronization request. Warning: This blocks shutdown requests while the thread is sleeping.
-    pub fn new(receiver: mpsc::Receiver<()>, run: Arc<AtomicBool>, sleep_time: Duration) -> Result<Synchronizer, SynchronizerCreationError> {
-        let desktop_path = match Self::steam_dir() {
+    /// `config` selects which games get launchers, where they go and what gets exported to Steam. Every synchronization uses the configuration active when it starts.
+    /// Its `poll_interval` controls how long the thread sleeps between checking for a synchronization request. Warning: This blocks shutdown requests while the thread is sleeping.
+    pub fn new(receiver: mpsc::Receiver<SyncRequest>, run: Arc<AtomicBool>, config: SharedConfig) -> Result<Synchronizer, SynchronizerCreationError> {
+        let desktop_path = match Self::desktop_dir(&config.get()) {
             Ok(val) => val,
             Err(_) => return Err(SynchronizerCreationError { kind: SynchronizerCreationErrorKind::NoHomeDir })
         };
@@ -65,12 +229,16 @@ impl Synchronizer {
             let w = Arc::clone(&working);
 
             // Worker thread to actually synchronize
+            let shared = config.clone();
             let worker = thread::spawn(move || loop {
                 match rx.recv() {
                     Ok(command) => match command {
-                        SynchronizerChildCommand::Run => {
-                            w.store(true, Ordering::SeqCst);
-                            Self::synchronize();
+                        SynchronizerChildCommand::Run(request) => {
+                            let active = shared.get();
+                            match request {
+                                SyncRequest::Full => Self::synchronize(&active),
+                                SyncRequest::Icons => Self::fix_icons(&active)
+                            }
                             w.store(false, Ordering::SeqCst);
                         },
                         SynchronizerChildCommand::Die => break
@@ -82,15 +250,17 @@ impl Synchronizer {
                 }
             });
 
-            // Makes sure multiple synchronizations aren't run at the same time
+            // Makes sure multiple synchronizations aren't run at the same time.
+            // Requests that arrive meanwhile are merged into one that runs once the current synchronization is done.
+            let mut pending = None;
             loop {
                 match receiver.try_recv() {
-                    Ok(_) => {
-                        if working.load(Ordering::SeqCst) {
-                            continue;
-                        }
-
-                        tx.send(SynchronizerChildCommand::Run).expect("Unable to send command to child synchronizer thread");
+                    Ok(request) => {
+                        // A full synchronization installs icons too
+                        pending = match (pending, request) {
+                            (Some(SyncRequest::Full), _) => Some(SyncRequest::Full),
+                            _ => Some(request)
+                        };
                     },
                     Err(e) => match e {
                         TryRecvError::Disconnected => {
@@ -104,7 +274,15 @@ impl Synchronizer {
                                 worker.join().expect("Unable to join worker thread");
                                 break;
                             }
-                            thread::sleep(sleep_time);
+                            if !working.load(Ordering::SeqCst) {
+                                if let Some(request) = pending.take() {
+                                    // Set here rather than by the worker, so the next request can't slip in before it starts
+                                    working.store(true, Ordering::SeqCst);
+                                    tx.send(SynchronizerChildCommand::Run(request)).expect("Unable to send command to child synchronizer thread");
+                                    continue;
+                                }
+                            }
+                            thread::sleep(config.get().poll_interval());
                         }
                     }
                 }
@@ -121,181 +299,744 @@ impl Synchronizer {
         }
     }
 
-    fn steam_dir() -> Result<PathBuf, VarError> {
-        let key = env::var("HOME")?;
-
-        Ok(Path::new(&key).join(".var/app/com.valvesoftware.Steam"))
-    }
+    fn desktop_dir(config: &Config) -> Result<PathBuf, VarError> {
+        if let Some(dir) = &config.applications_dir {
+            return Ok(dir.clone());
+        }
 
-    fn desktop_dir() -> Result<PathBuf, VarError> {
         let key = env::var("HOME")?;
 
         Ok(Path::new(&key).join(".local/share/applications"))
     }
 
-    fn icons_dir() -> Result<PathBuf, VarError> {
-        let key = env::var("HOME")?;
+    /// The user's hicolor icon theme, `$XDG_DATA_HOME/icons/hicolor` unless configured otherwise.
+    fn icons_dir(config: &Config) -> Result<PathBuf, VarError> {
+        if let Some(dir) = &config.icons_dir {
+            return Ok(dir.clone());
+        }
+
+        let data_home = match env::var("XDG_DATA_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => Path::new(&env::var("HOME")?).join(".local/share")
+        };
 
-        Ok(Path::new(&key).join(".icons"))
+        Ok(data_home.join("icons/hicolor"))
     }
 
-    fn icon_from_id(id: &str, root: &PathBuf) -> Option<PathBuf> {
-        let icon_filename = format!("steam_icon_{}.png", id);
+    /// Finds every size of every app's icon below `root` in a single pass.
+    fn index_theme_icons(root: &Path) -> ThemeIcons {
+        let mut index = ThemeIcons::new();
 
-        // Recursively search for icon
-        let mut entries: Vec<_> = WalkDir::new(root)
-            .into_iter()
-            .filter_map(|f| f.ok())
-            .filter(|f| f.file_name().to_str().unwrap_or("") == icon_filename)
-            .filter_map(|path| {
-                if let Some(s) = path.into_path().to_str() {
-                    return Some(String::from(s));
-                }
-                None
-            })
-            .collect();
+        for f in WalkDir::new(root).into_iter().filter_map(|f| f.ok()) {
+            let id = match f.file_name().to_str().and_then(|n| n.strip_prefix("steam_icon_")?.strip_suffix(".png")) {
+                Some(id) => id.to_string(),
+                None => continue
+            };
+            if let Some((width, height)) = Self::icon_dimensions(f.path()) {
+                index.entry(id).or_default().push((width, height, f.into_path()));
+            }
+        }
 
-        if entries.len() == 1 {
-            return Some(Path::new(&entries.pop().unwrap()).to_path_buf())
-        } else if entries.len() == 0 {
-            return None
+        for icons in index.values_mut() {
+            // Sorted by size, since path order would put 64x64 after 256x256
+            icons.sort_by(|a, b| (b.0 * b.1).cmp(&(a.0 * a.1)).then_with(|| a.2.cmp(&b.2)));
+            icons.dedup_by(|a, b| a.0 == b.0 && a.1 == b.1);
         }
 
-        // Find highest-res icon
-        entries.sort();
+        index
+    }
+
+    /// Reads the dimensions of an icon from its PNG header, or from the `<width>x<height>` theme directory it is in.
+    fn icon_dimensions(path: &Path) -> Option<(u32, u32)> {
+        let mut header = [0; 24];
+        let from_header = fs::File::open(path)
+            .and_then(|mut f| f.read_exact(&mut header))
+            .ok()
+            .filter(|_| header.starts_with(b"\x89PNG\r\n\x1a\n") && &header[12..16] == b"IHDR")
+            .map(|_| (
+                u32::from_be_bytes([header[16], header[17], header[18], header[19]]),
+                u32::from_be_bytes([header[20], header[21], header[22], header[23]])
+            ));
+        if from_header.is_some() {
+            return from_header;
+        }
 
-        Some(Path::new(&entries.pop().unwrap()).to_path_buf())
+        // Themes keep icons in <size>/apps
+        let size = path.parent()?.parent()?.file_name()?.to_str()?;
+        let (width, height) = size.split_once('x')?;
+        Some((width.parse().ok()?, height.parse().ok()?))
     }
 
-    fn load_desktop_files(src: &PathBuf) -> Vec<SteamDesktopFile> {
+    fn load_desktop_files(src: &Path) -> Vec<SteamDesktopFile> {
         lazy_static! {
-            static ref EXEC_REGEX: Regex = Regex::new("^Exec=.*steam://rungameid/[0-9]+$").unwrap();
-            static ref NAME_REGEX: Regex = Regex::new("^Name=.+$").unwrap();
+            static ref RUNGAMEID_REGEX: Regex = Regex::new("steam://rungameid/([0-9]+)\\s*$").unwrap();
         };
 
         WalkDir::new(src)
             .into_iter()
             .filter_map(|f| f.ok())
-            .filter_map(|f| fs::read_to_string(f.path()).ok())
-            .filter_map(|contents| {
-                let mut name = "";
-                let mut exec = "";
-                for line in contents.lines() {
-                    if EXEC_REGEX.is_match(line) {
-                        exec = line;
-                    } else if NAME_REGEX.is_match(line) {
-                        name = line;
+            .filter(|f| f.path().extension().map(|e| e == "desktop").unwrap_or(false))
+            .filter_map(|f| {
+                let contents = fs::read_to_string(f.path()).ok()?;
+                match DesktopEntry::parse(&contents) {
+                    Ok(entry) => Some((f.into_path(), entry)),
+                    Err(e) => {
+                        eprintln!("Unable to parse desktop file {}: {}", f.path().display(), e);
+                        None
                     }
                 }
-                
-                if exec.len() > 0 && name.len() > 0 {
-                    let last_slash = exec.rfind('/').unwrap();
+            })
+            .filter_map(|(path, entry)| {
+                let name = entry.name().filter(|n| !n.is_empty())?;
+                let id = match entry.desktop_entry().and_then(|g| g.get_string(APP_ID_KEY)) {
+                    Some(id) => id,
+                    None => RUNGAMEID_REGEX.captures(&entry.exec()?)?.get(1)?.as_str().to_string()
+                };
 
-                    Some(SteamDesktopFile {
-                        name: name[5..].to_string(),
-                        id: exec[last_slash + 1..].parse().unwrap()
-                    })
-                } else {
-                    None
-                }
+                let mut desktop = SteamDesktopFile::new(name, id);
+                desktop.entry = Some(entry);
+                desktop.path = Some(path);
+                Some(desktop)
             })
             .collect()
     }
 
-    fn write_desktop_file(file: &SteamDesktopFile, dest_dir: &PathBuf) -> Result<(), Error> {
-        let contents = format!("{}\n{}{}\n{}{}\n{}\n{}{}",
-            "[Desktop Entry]",
-            "Name=",
-            file.name,
-            "Icon=steam_icon_",
-            file.id,
-            "Type=Application",
-            "Exec=xdg-open steam://rungameid/",
-            file.id
-        );
+    fn render_desktop_file(file: &SteamDesktopFile, icon: &str, config: &Config, learned: &LearnedClasses) -> String {
+        // Everything Steam wrote is kept, except what has to change to work outside of its installation
+        let mut entry = file.entry.clone().unwrap_or_default();
+        let group = entry.desktop_entry_mut();
+
+        group.set("Type", "Application");
+        group.set_string("Name", &file.name);
+        for (locale, name) in &file.localized_names {
+            group.set_localized("Name", locale, name);
+        }
+        group.set_string("Icon", icon);
+        let game = config.overrides.get(&file.id);
+        let target = ExecTarget { id: &file.id, name: game.and_then(|g| g.name.as_deref()).unwrap_or(&file.name), kind: file.kind };
+        group.set_string("Exec", &config.exec_for(&target));
+        group.set_string(APP_ID_KEY, &file.id);
+
+        // Docks tie windows to the launcher with the same class
+        let wm_class = config.overrides.get(&file.id).and_then(|g| g.wm_class.clone())
+            .or_else(|| learned.get(&file.id).map(String::from))
+            .unwrap_or_else(|| wm_class::default_class(&file.id));
+        group.set_string("StartupWMClass", &wm_class);
+
+        let labels: Vec<String> = file.genres.iter().chain(&file.tags).cloned().collect();
+        group.set_strings("Categories", &categories::categories_for(file.app_type.as_ref(), &labels, &config.categories));
+        let keywords = categories::keywords_for(&file.tags, &file.genres);
+        if !keywords.is_empty() {
+            group.set_strings("Keywords", &keywords);
+        }
+
+        if let Some(game) = game {
+            if let Some(name) = &game.name {
+                group.set_string("Name", name);
+                group.remove_localized("Name");
+            }
+            if let Some(icon) = &game.icon {
+                group.set_string("Icon", icon);
+            }
+            if game.hidden {
+                group.set_bool("NoDisplay", true);
+            }
+        }
+
+        // Store pages and the like only exist for Steam apps, not for non-Steam shortcuts
+        entry.clear_actions();
+        if file.id.parse::<u32>().is_ok() {
+            for action in &config.actions {
+                entry.set_action(action.id(), action.name(), &config.open_url(&target, &action.url(&file.id)));
+            }
+        }
 
-        fs::write(dest_dir.join(format!("{}.desktop", file.name)), contents)
+        entry.to_string()
     }
 
-    fn synchronize() {
-        println!("Starting Synchronization");
-        // Load indexed vs steam to see what needs to be added or removed
-        let steam_path = Self::steam_dir().expect("Failed to load steam desktop dir files");
-        let steam = Self::load_desktop_files(&steam_path.join("data/applications"));
-        let desktop_path = Self::desktop_dir().expect("Failed to load desktop dir files");
-        let icons_path = Self::icons_dir().expect("Failed to find icons dir");
-        let indexed = Self::load_desktop_files(&desktop_path);
+    /// Renames files written under the display name of their app by earlier versions to their stable name.
+    ///
+    /// Only files the daemon wrote are renamed, including ones that were edited afterwards.
+    fn migrate_file_names(indexed: &mut [SteamDesktopFile], desktop_path: &Path, state: &mut SyncState) {
+        for desktop in indexed.iter_mut() {
+            let path = match &desktop.path {
+                Some(path) if state.get(path).is_some() => path.clone(),
+                _ => continue
+            };
 
-        let mut map = HashMap::new();
+            let dest = desktop_path.join(desktop.file_name());
+            if path == dest || dest.exists() {
+                continue;
+            }
 
-        for desktop in steam.into_iter() {
-            map.insert(desktop, 1);
+            println!("Renaming desktop file {} to {}", path.display(), dest.display());
+            match fs::rename(&path, &dest) {
+                Ok(_) => {
+                    state.rename(&path, &dest);
+                    desktop.path = Some(dest);
+                },
+                Err(e) => eprintln!("Unable to rename desktop file {}: {}", path.display(), e)
+            }
         }
+    }
 
-        for desktop in indexed.into_iter() {
-            if let Some(&cnt) = map.get(&desktop) {
-                map.insert(desktop, cnt - 1);
-            } else {
-                map.insert(desktop, -1);
+    fn load_installed_apps(root: &SteamRoot) -> Vec<InstalledApp> {
+        match library::installed_apps(&root.install_dir) {
+            Ok(apps) => apps.into_iter().filter(|a| a.is_fully_installed()).collect(),
+            Err(e) => {
+                eprintln!("Unable to load libraries of {}: {:?}", root.install_dir.display(), e.kind);
+                Vec::new()
             }
         }
+    }
 
-        // Remove old entries/icons and convert new entries/icons
-        for (desktop_file, cnt) in map.into_iter() {
-            if cnt >= 1 {
-                println!("Adding desktop file {}", desktop_file.name);
-                match Self::write_desktop_file(&desktop_file, &desktop_path) {
-                    Ok(_) => {},
-                    Err(e) => {
-                        eprintln!("Unable to write desktop file {}: {}", &desktop_file.name, e);
-                        continue;
-                    }
+    fn load_shortcuts(root: &SteamRoot, desktop_path: &Path) -> Vec<SteamDesktopFile> {
+        let applications = root.applications_dir();
+
+        // Native Steam writes straight into the destination, so there is nothing to copy
+        if fs::canonicalize(&applications).ok() == fs::canonicalize(desktop_path).ok() {
+            return Vec::new();
+        }
+
+        // Only games with a manifest are really installed, unless no manifests could be read at all
+        let installed: HashMap<String, PathBuf> = Self::load_installed_apps(root)
+            .into_iter()
+            .map(|a| (a.appid.to_string(), a.library))
+            .collect();
+
+        Self::load_desktop_files(&applications)
+            .into_iter()
+            .filter_map(|mut desktop| {
+                if !installed.is_empty() && !installed.contains_key(&desktop.id) {
+                    println!("Skipping desktop file {}: app {} is not installed", desktop.name, desktop.id);
+                    return None;
+                }
+                desktop.library = installed.get(&desktop.id).cloned();
+                Some(desktop)
+            })
+            .collect()
+    }
+
+    /// Exports `exported` into every user's `shortcuts.vdf`, and removes the shortcuts exported earlier whose `.desktop` file is gone.
+    fn export_shortcuts(roots: &[SteamRoot], exported: &[NonSteamShortcut], state: &mut SyncState) {
+        for root in roots {
+            for config_dir in shortcuts::user_config_dirs(&root.install_dir) {
+                let path = config_dir.join("shortcuts.vdf");
+                let stale: Vec<(u32, String)> = state.shortcuts_in(&path)
+                    .filter(|(appid, _)| !exported.iter().any(|s| s.appid == *appid))
+                    .map(|(appid, hash)| (appid, hash.to_string()))
+                    .collect();
+                if exported.is_empty() && stale.is_empty() {
+                    continue;
+                }
+
+                match shortcuts::save_shortcuts(&path, exported, &stale) {
+                    Ok(changed) => {
+                        if changed {
+                            println!("Exported {} shortcuts to {}", exported.len(), path.display());
+                        }
+                        // Stale shortcuts that were changed in Steam are kept, and left to the user from now on
+                        for (appid, _) in &stale {
+                            state.forget_shortcut(&path, *appid);
+                        }
+                        for shortcut in exported {
+                            state.record_shortcut(&path, shortcut.appid, &shortcut.hash());
+                        }
+                    },
+                    Err(ShortcutWriteError { kind: ShortcutWriteErrorKind::SteamRunning }) => {
+                        println!("Steam is running, postponing shortcut export");
+                        return;
+                    },
+                    Err(e) => eprintln!("Unable to export shortcuts to {}: {:?}", path.display(), e.kind)
+                }
+            }
+        }
+    }
+
+    /// Drops runtimes, tools and other apps nobody launches directly, and adds translated names.
+    fn apply_appinfo(root: &SteamRoot, desktops: Vec<SteamDesktopFile>) -> Vec<SteamDesktopFile> {
+        let appids: HashSet<u32> = desktops.iter().filter_map(|d| d.id.parse().ok()).collect();
+        let infos = match appinfo::load_appinfo(&root.install_dir, Some(&appids)) {
+            Ok(infos) => infos,
+            Err(e) => {
+                eprintln!("Unable to read app info of {}: {:?}", root.install_dir.display(), e.kind);
+                return desktops;
+            }
+        };
+
+        desktops.into_iter()
+            .filter_map(|mut desktop| {
+                let info = match desktop.id.parse().ok().and_then(|id: u32| infos.get(&id)) {
+                    Some(info) => info,
+                    None => return Some(desktop)
                 };
 
-                // Write icon
-                if let Some(path) = Self::icon_from_id(&desktop_file.id, &steam_path.join("data/icons/hicolor")) {
-                    match fs::create_dir_all(&icons_path){
-                        Ok(_) => {},
-                        Err(e) => {
-                            eprintln!("Failed to create icons directory at {}: {}", icons_path.to_str().unwrap_or("ERROR!"), e);
-                            continue;
+                if !info.app_type.is_launchable() {
+                    println!("Skipping desktop file {}: app {} is a {}", desktop.name, desktop.id, info.app_type);
+                    return None;
+                }
+
+                desktop.localized_names = info.names_by_locale()
+                    .into_iter()
+                    .filter(|(_, name)| *name != desktop.name)
+                    .map(|(locale, name)| (locale.to_string(), name.to_string()))
+                    .collect();
+                desktop.app_type = Some(info.app_type.clone());
+                desktop.genres = info.genres.clone();
+                desktop.tags = info.store_tag_names().into_iter().map(String::from).collect();
+                Some(desktop)
+            })
+            .collect()
+    }
+
+    /// Loads the state, adopting files that were written before it existed.
+    fn load_state(indexed: &[SteamDesktopFile]) -> Option<SyncState> {
+        let mut state = match SyncState::load() {
+            Ok(state) => state,
+            Err(e) => {
+                eprintln!("Unable to load state: {:?}", e.kind);
+                return None;
+            }
+        };
+
+        state.prune();
+
+        let legacy_icons_dir = env::var("HOME").map(|home| Path::new(&home).join(".icons")).ok();
+        Self::adopt_legacy_files(&mut state, indexed, legacy_icons_dir.as_deref());
+
+        Some(state)
+    }
+
+    /// Records the files written before the state file existed, which are recognized by their exact contents, as owned.
+    fn adopt_legacy_files(state: &mut SyncState, indexed: &[SteamDesktopFile], legacy_icons_dir: Option<&Path>) {
+        for desktop in indexed {
+            if let Some(path) = desktop.path.as_ref().filter(|p| state.get(p).is_none()) {
+                if let Ok(contents) = fs::read_to_string(path) {
+                    if desktop.is_legacy(&contents) {
+                        state.record(path, ArtifactKind::DesktopFile, &desktop.id, contents.as_bytes());
+
+                        // Those versions copied the icon next to the launcher into ~/.icons
+                        let icon = legacy_icons_dir.map(|d| d.join(format!("steam_icon_{}.png", desktop.id)));
+                        if let Some((icon, contents)) = icon.and_then(|i| fs::read(&i).ok().map(|c| (i, c))) {
+                            if state.get(&icon).is_none() {
+                                state.record(&icon, ArtifactKind::Icon, &desktop.id, &contents);
+                            }
                         }
                     }
+                }
+            }
+        }
+    }
 
-                    match fs::copy(&path, &icons_path.join(&path.file_name().unwrap())) {
-                        Ok(_) => {},
-                        Err(e) => eprintln!("Unable to write icon for desktop file {}: {}", desktop_file.name, e)
-                    }
-                } else {
-                    eprintln!("No icon found for desktop file {}", desktop_file.name);
+    /// Collects the launchers that should exist for every root, keeping the first one seen for each app, and the apps the filter rules exclude.
+    ///
+    /// Icons converted from artwork are taken from `icons_path` instead when `state` shows they were made from the same image.
+    fn load_sources(config: &Config, roots: &[SteamRoot], desktop_path: &Path, icons_path: &Path, state: &SyncState, exported_ids: &HashSet<u32>, learned: &LearnedClasses) -> (Vec<SourceEntry>, Vec<ExcludedEntry>) {
+        let mut steam = Vec::new();
+        let mut icon_sources = HashMap::new();
+        let mut seen = HashSet::new();
+        for root in roots {
+            let desktops = match config.source {
+                SyncSource::Shortcuts => Self::load_shortcuts(root, desktop_path),
+                SyncSource::InstalledApps => Self::load_installed_apps(root)
+                    .into_iter()
+                    .map(|app| {
+                        let mut desktop = SteamDesktopFile::new(app.name, app.appid.to_string());
+                        desktop.library = Some(app.library);
+                        desktop
+                    })
+                    .collect()
+            };
+            let desktops = Self::apply_appinfo(root, desktops);
+
+            for mut desktop in desktops {
+                desktop.kind = root.kind;
+                if seen.insert(desktop.id.clone()) {
+                    icon_sources.insert(desktop.id.clone(), IconSource {
+                        theme: root.icons_dir(),
+                        artwork: icons::app_artwork(&root.install_dir, &desktop.id),
+                        file: None
+                    });
+                    steam.push(desktop);
                 }
-            } else if cnt <= -1 { // Remove file
-                println!("Removing desktop file {}", desktop_file.name);
-                match fs::remove_file(desktop_path.join(format!("{}.desktop", desktop_file.name))) {
-                    Ok(_) => {},
-                    Err(e) => eprintln!("Unable to remove desktop file {}: {}", desktop_file.name, e)
-                };
+            }
 
-                // Remove icon file if possible
-                if let Some(path) = Self::icon_from_id(&desktop_file.id, &Path::new("/usr/share/pixmaps").to_path_buf()) {
-                    match fs::remove_file(path) {
-                        Ok(_) => {},
-                        Err(e) => eprintln!("Unable to remove icon for desktop file {}: {}", desktop_file.name, e)
-                    }
+            // Non-Steam games are launched through Steam with their 64-bit game id
+            for shortcut in shortcuts::load_shortcuts(&root.install_dir) {
+                if shortcut.is_hidden || exported_ids.contains(&shortcut.appid) {
+                    continue;
+                }
+
+                let mut desktop = SteamDesktopFile::new(shortcut.app_name.clone(), shortcut.game_id().to_string());
+                desktop.kind = root.kind;
+                if seen.insert(desktop.id.clone()) {
+                    icon_sources.insert(desktop.id.clone(), IconSource {
+                        theme: root.icons_dir(),
+                        artwork: icons::shortcut_artwork(&root.install_dir, &shortcut),
+                        file: shortcut.icon_path()
+                    });
+                    steam.push(desktop);
                 }
+            }
+        }
+
+        let filter = match Filter::new(&config.filters) {
+            Ok(filter) => filter,
+            Err(e) => {
+                eprintln!("Ignoring filter rules: {}", e);
+                Filter::new(&[]).expect("No rules always compile")
+            }
+        };
+        let mut excluded = Vec::new();
+        steam.retain(|desktop| {
+            let subject = FilterSubject { id: &desktop.id, name: &desktop.name, app_type: desktop.app_type.as_ref(), library: desktop.library.as_deref() };
+            match filter.excludes(&subject) {
+                Some(reason) => {
+                    excluded.push(ExcludedEntry { app_id: desktop.id.clone(), name: desktop.name.clone(), reason });
+                    false
+                },
+                None => true
+            }
+        });
+
+        // Themes are only searched once, rather than once for every app
+        let mut theme_icons: HashMap<PathBuf, ThemeIcons> = HashMap::new();
+        let sources = steam.into_iter().map(|desktop_file| {
+            let theme_icon = format!("steam_icon_{}", desktop_file.id);
+            let source = &icon_sources[&desktop_file.id];
+            let game = config.overrides.get(&desktop_file.id);
+            let custom_icon = game.map(|g| g.icon.is_some()).unwrap_or(false);
+
+            // An icon of the user's own replaces Steam's, so those aren't installed
+            let icons = if custom_icon {
+                Vec::new()
             } else {
-                println!("Skipping desktop file {}", desktop_file.name);
+                let theme = theme_icons.entry(source.theme.clone()).or_insert_with(|| Self::index_theme_icons(&source.theme));
+                Self::load_icons(&desktop_file, theme, source, config, state, icons_path)
+            };
+
+            // Images that can't be converted are referenced where they are
+            let icon_name = match source.file.as_ref().filter(|p| p.is_file()) {
+                Some(path) if icons.is_empty() => path.to_string_lossy().into_owned(),
+                _ => {
+                    if icons.is_empty() && !custom_icon {
+                        eprintln!("No icon found for desktop file {}", desktop_file.name);
+                    }
+                    theme_icon
+                }
+            };
+
+            SourceEntry {
+                app_id: desktop_file.id.clone(),
+                name: game.and_then(|g| g.name.clone()).unwrap_or_else(|| desktop_file.name.clone()),
+                file_name: desktop_file.file_name(),
+                contents: Self::render_desktop_file(&desktop_file, &icon_name, config, learned),
+                icons
+            }
+        }).collect();
+
+        (sources, excluded)
+    }
+
+    /// Collects the icons of a launcher at every size, made from other artwork if Steam didn't install any.
+    fn load_icons(desktop_file: &SteamDesktopFile, theme: &ThemeIcons, source: &IconSource, config: &Config, state: &SyncState, icons_path: &Path) -> Vec<SourceIcon> {
+        let theme_icon = format!("steam_icon_{}", desktop_file.id);
+
+        // Every size is installed, so the desktop can pick a sharp one at any scale
+        let icons: Vec<SourceIcon> = theme.get(&desktop_file.id).into_iter().flatten().filter_map(|(width, height, path)| {
+            match fs::read(path) {
+                Ok(contents) => Some(SourceIcon {
+                    path: PathBuf::from(format!("{}x{}/apps/{}.png", width, height, theme_icon)),
+                    src: path.clone(),
+                    hash: state::hash_contents(&contents),
+                    source_hash: None,
+                    contents
+                }),
+                Err(e) => {
+                    eprintln!("Unable to read icon {} for desktop file {}: {}", path.display(), desktop_file.name, e);
+                    None
+                }
+            }
+        }).collect();
+
+        if !icons.is_empty() || !config.fallback_icons {
+            return icons;
+        }
+
+        // Steam doesn't install icons for every game, so they are made from its other artwork
+        for art in &source.artwork {
+            let source_hash = match fs::read(art) {
+                Ok(contents) => state::hash_contents(&contents),
+                Err(_) => continue
+            };
+
+            // Rendering is slow, so icons made from the same image before are kept as they are
+            if let Some(installed) = Self::installed_icons(&desktop_file.id, art, &source_hash, state, icons_path) {
+                return installed;
+            }
+
+            if let Ok(rendered) = icons::render_icon(art) {
+                return rendered.into_iter().map(|icon| SourceIcon {
+                    path: PathBuf::from(format!("{0}x{0}/apps/{1}.png", icon.size, theme_icon)),
+                    src: art.clone(),
+                    hash: state::hash_contents(&icon.png),
+                    source_hash: Some(source_hash.clone()),
+                    contents: icon.png
+                }).collect();
+            }
+        }
+
+        Vec::new()
+    }
+
+    /// Returns the icons installed for `app_id`, if every one was made from the image `src` whose SHA-256 is `source_hash` and is unchanged since.
+    fn installed_icons(app_id: &str, src: &Path, source_hash: &str, state: &SyncState, icons_path: &Path) -> Option<Vec<SourceIcon>> {
+        let installed: Vec<(&Path, &OwnedArtifact)> = state.artifacts_for(app_id).filter(|(_, a)| a.kind == ArtifactKind::Icon).collect();
+        if installed.is_empty() {
+            return None;
+        }
+
+        installed.into_iter().map(|(path, artifact)| {
+            if artifact.source.as_deref() != Some(source_hash) {
+                return None;
+            }
+
+            let contents = fs::read(path).ok()?;
+            let hash = state::hash_contents(&contents);
+            if hash != artifact.hash {
+                return None;
+            }
+
+            Some(SourceIcon {
+                src: src.to_path_buf(),
+                path: path.strip_prefix(icons_path).ok()?.to_path_buf(),
+                hash,
+                source_hash: Some(source_hash.to_string()),
+                contents
+            })
+        }).collect()
+    }
+
+    /// Snapshots the launchers and icons a plan for `sources` depends on.
+    fn load_destination(desktop_path: &Path, icons_path: &Path, indexed: &[SteamDesktopFile], sources: &[SourceEntry], state: &SyncState) -> Destination {
+        let mut destination = Destination { applications_dir: desktop_path.to_path_buf(), icons_dir: icons_path.to_path_buf(), ..Default::default() };
+
+        for desktop in indexed {
+            let path = match &desktop.path {
+                Some(path) => path,
+                None => continue
+            };
+
+            destination.entries.push(ExistingEntry {
+                app_id: desktop.id.clone(),
+                name: desktop.name.clone(),
+                path: path.clone(),
+                contents: fs::read_to_string(path).unwrap_or_default(),
+                ownership: state.ownership(path)
+            });
+        }
+
+        for (path, artifact) in state.iter().filter(|(_, a)| a.kind == ArtifactKind::Icon) {
+            destination.icons.entry(artifact.app_id.clone()).or_default().push(path.to_path_buf());
+        }
+
+        let paths = sources.iter()
+            .flat_map(|s| std::iter::once(desktop_path.join(&s.file_name)).chain(s.icons.iter().map(|i| icons_path.join(&i.path))))
+            .chain(destination.icons.values().flatten().cloned())
+            .collect::<Vec<_>>();
+        for path in paths {
+            if let Some(file) = plan::existing_file(&path, state) {
+                destination.files.insert(path, file);
+            }
+        }
+
+        destination
+    }
+
+    /// Works out what a synchronization would change, without changing anything.
+    fn plan(config: &Config) -> Option<SyncPlan> {
+        let _lock = SYNC_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let roots = steam::discover_steam_roots().ok()?;
+        let desktop_path = Self::desktop_dir(config).ok()?;
+        let icons_path = Self::icons_dir(config).ok()?;
+        let indexed = Self::load_desktop_files(&desktop_path);
+        let state = Self::load_state(&indexed)?;
+
+        let exported = export::exported_shortcuts(config.export_dir.as_deref(), &desktop_path);
+        // Stale exports stay in Steam until it is closed, and must not be mirrored back either
+        let exported_ids: HashSet<u32> = exported.iter().map(|s| s.appid).chain(state.shortcut_ids()).collect();
+
+        let (sources, excluded) = Self::load_sources(config, &roots, &desktop_path, &icons_path, &state, &exported_ids, &LearnedClasses::load_or_empty());
+        let destination = Self::load_destination(&desktop_path, &icons_path, &indexed, &sources, &state);
+
+        Some(SyncPlan::compute(&sources, &excluded, &destination, config))
+    }
+
+    /// Installs icons for launchers the daemon wrote that don't have any, leaving everything else alone.
+    fn fix_icons(config: &Config) {
+        let _lock = SYNC_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+
+        let roots = steam::discover_steam_roots().unwrap_or_default();
+        let desktop_path = Self::desktop_dir(config).expect("Failed to load desktop dir files");
+        let icons_path = Self::icons_dir(config).expect("Failed to find icons dir");
+        let indexed = Self::load_desktop_files(&desktop_path);
+
+        let mut state = match Self::load_state(&indexed) {
+            Some(state) => state,
+            None => return
+        };
+
+        // Only launchers that use a theme icon and have nothing installed for it need fixing
+        let missing: Vec<&SteamDesktopFile> = indexed.iter()
+            .filter(|d| d.path.as_ref().map(|p| state.ownership(p) == Ownership::Owned).unwrap_or(false))
+            .filter(|d| d.entry.as_ref().and_then(|e| e.icon()) == Some(format!("steam_icon_{}", d.id)))
+            .filter(|d| !state.artifacts_for(&d.id).any(|(_, a)| a.kind == ArtifactKind::Icon))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+
+        let mut theme_icons: HashMap<PathBuf, ThemeIcons> = HashMap::new();
+        let sources: Vec<SourceEntry> = missing.iter().map(|desktop_file| {
+            let icons = roots.iter().find_map(|root| {
+                let shortcut = shortcuts::load_shortcuts(&root.install_dir).into_iter().find(|s| s.game_id().to_string() == desktop_file.id);
+                let source = IconSource {
+                    theme: root.icons_dir(),
+                    artwork: match &shortcut {
+                        Some(shortcut) => icons::shortcut_artwork(&root.install_dir, shortcut),
+                        None => icons::app_artwork(&root.install_dir, &desktop_file.id)
+                    },
+                    file: None
+                };
+
+                let theme = theme_icons.entry(source.theme.clone()).or_insert_with(|| Self::index_theme_icons(&source.theme));
+                Some(Self::load_icons(desktop_file, theme, &source, config, &state, &icons_path)).filter(|icons| !icons.is_empty())
+            }).unwrap_or_default();
+
+            SourceEntry {
+                app_id: desktop_file.id.clone(),
+                name: desktop_file.name.clone(),
+                file_name: desktop_file.file_name(),
+                contents: String::new(),
+                icons
+            }
+        }).collect();
+
+        let destination = Self::load_destination(&desktop_path, &icons_path, &indexed, &sources, &state);
+        let plan = SyncPlan::compute_icons(&sources, &destination);
+        if plan.is_empty() {
+            return;
+        }
+
+        println!("Installing missing icons");
+        plan.apply(&mut state);
+
+        if let Err(e) = state.save() {
+            eprintln!("Unable to save state: {}", e);
+        }
+    }
+
+    /// Registers a `steam://` handler for the first of `roots` that can run, unless something installed handles those URLs already.
+    fn check_url_handler(roots: &[SteamRoot]) {
+        let root = match roots.iter().find(|r| url_handler::can_run(r.kind)) {
+            Some(root) => root,
+            None => return
+        };
+        let dirs = match XdgDirs::from_env() {
+            Ok(dirs) => dirs,
+            Err(e) => {
+                eprintln!("Unable to find XDG directories: {}", e);
+                return;
+            }
+        };
+
+        let status = url_handler::status(&dirs);
+        if status.is_working() {
+            return;
+        }
+        println!("steam:// links don't work: {}", status);
+
+        let mut record = match HandlerRecord::load() {
+            Ok(record) => record,
+            Err(e) => {
+                eprintln!("Unable to load the steam:// handler record: {:?}", e.kind);
+                return;
             }
+        };
+
+        // Whatever was changed is recorded, even if registering failed halfway
+        let registered = url_handler::register(&dirs, root, &mut record);
+        if let Err(e) = record.save() {
+            eprintln!("Unable to save the steam:// handler record: {}", e);
+        }
+        match registered {
+            Ok(path) => println!("Registered {} as the steam:// handler", path.display()),
+            Err(e) => eprintln!("Unable to register a steam:// handler: {}", e)
+        }
+    }
+
+    fn synchronize(config: &Config) {
+        let _lock = SYNC_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        println!("Starting Synchronization");
+        // Load indexed vs steam to see what needs to be added or removed
+        let roots = steam::discover_steam_roots().expect("Failed to discover steam roots");
+        let desktop_path = Self::desktop_dir(config).expect("Failed to load desktop dir files");
+        let icons_path = Self::icons_dir(config).expect("Failed to find icons dir");
+        // A reloaded configuration can point somewhere new
+        if let Err(e) = fs::create_dir_all(&desktop_path) {
+            eprintln!("Unable to create {}: {}", desktop_path.display(), e);
+            eprintln!("Aborting synchronization");
+            return;
         }
+        let mut indexed = Self::load_desktop_files(&desktop_path);
 
-        // Update
-        Command::new("update-desktop-database").arg(&desktop_path).status().expect("Failed to update desktop database");
+        if roots.is_empty() {
+            eprintln!("No Steam installation found");
+        }
+
+        let mut state = match Self::load_state(&indexed) {
+            Some(state) => state,
+            None => {
+                eprintln!("Aborting synchronization");
+                return;
+            }
+        };
+
+        Self::migrate_file_names(&mut indexed, &desktop_path, &mut state);
+
+        // Shortcuts exported from desktop files already have launchers, so they must not be mirrored back
+        let exported = export::exported_shortcuts(config.export_dir.as_deref(), &desktop_path);
+        // Stale exports stay in Steam until it is closed, and must not be mirrored back either
+        let exported_ids: HashSet<u32> = exported.iter().map(|s| s.appid).chain(state.shortcut_ids()).collect();
+
+        let (sources, excluded) = Self::load_sources(config, &roots, &desktop_path, &icons_path, &state, &exported_ids, &LearnedClasses::load_or_empty());
+        let destination = Self::load_destination(&desktop_path, &icons_path, &indexed, &sources, &state);
+
+        SyncPlan::compute(&sources, &excluded, &destination, config).apply(&mut state);
+
+        // Icons that were up to date already get the image they were made from recorded, so they aren't rendered again
+        for icon in sources.iter().flat_map(|s| &s.icons) {
+            if let Some(source_hash) = &icon.source_hash {
+                state.record_source(&icons_path.join(&icon.path), &icon.hash, source_hash);
+            }
+        }
+
+        Self::export_shortcuts(&roots, &exported, &mut state);
+
+        if let Err(e) = state.save() {
+            eprintln!("Unable to save state: {}", e);
+        }
+
+        // Launchers that run Steam themselves work without a handler, so associations are only touched when one is needed
+        if config.register_url_handler && config.uses_url_handler() {
+            Self::check_url_handler(&roots);
+        }
 
         println!("Synchronization Complete");
     }
 }
 
-/// Watches Steam's Flatpak applications directory for `.desktop` file changes and triggers a synchronization when they do.
+/// Watches the applications directory of every detected Steam installation for `.desktop` file changes and triggers a synchronization when they do.
 pub struct FileChangeListener {
     thread: Option<thread::JoinHandle<()>>
 }
@@ -310,50 +1051,144 @@ pub struct FileChangeListenerCreationError {
 pub enum FileChangeListenerCreationErrorKind {
     /// The `HOME` environment variable is not defined.
     NoHomeDir,
-    /// There is no valid steam installation at `$HOME/.var/app/com.valvesoftware.Steam`.
+    /// No Steam installation was found.
     NoSteamDir,
-    /// An error occured when trying to watch the Steam `applications` directory.
+    /// An error occured when trying to watch a Steam `applications` directory.
     UnableToWatch
 }
 
+/// The directories a `FileChangeListener` watches for one configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct WatchTargets {
+    /// Directories watched with everything below them.
+    recursive: Vec<PathBuf>,
+    /// Directories whose files matter but whose subdirectories don't.
+    flat: Vec<PathBuf>,
+    /// Steam's icons and artwork, whose changes only need an icon fixup.
+    icons: Vec<PathBuf>,
+}
+
+impl WatchTargets {
+    fn find_in(config: &Config, roots: &[SteamRoot]) -> WatchTargets {
+        let desktop_path = Synchronizer::desktop_dir(config).ok().and_then(|p| fs::canonicalize(p).ok());
+
+        // Native Steam writes into the destination directory, which must not be watched or every sync would trigger another
+        let mut recursive: Vec<PathBuf> = roots.iter()
+            .map(|r| r.applications_dir())
+            .filter(|p| p.is_dir())
+            .filter(|p| fs::canonicalize(p).ok() != desktop_path)
+            .collect();
+
+        // Watching all of `userdata` also catches the `shortcuts.vdf` of a user who had none yet
+        recursive.extend(roots.iter().map(|r| r.install_dir.join("userdata")).filter(|p| p.is_dir()));
+
+        let mut flat: Vec<PathBuf> = Vec::new();
+        for root in roots {
+            if config.source == SyncSource::InstalledApps {
+                match library::load_libraries(&root.install_dir) {
+                    Ok(libraries) => flat.extend(libraries.into_iter().map(|l| l.path.join("steamapps"))),
+                    Err(e) => eprintln!("Unable to load libraries of {}: {:?}", root.install_dir.display(), e.kind)
+                }
+            }
+        }
+        flat.sort();
+        flat.dedup();
+        recursive.dedup();
+
+        if let Some(export_dir) = config.export_dir.as_ref().filter(|d| d.is_dir()) {
+            recursive.push(export_dir.clone());
+        }
+
+        // Steam often writes icons and artwork after the launcher, so they get a watch of their own, except where icons are installed to
+        let icons_path = Synchronizer::icons_dir(config).ok().and_then(|p| fs::canonicalize(p).ok());
+        let icons: Vec<PathBuf> = roots.iter()
+            .flat_map(|r| [r.icons_dir(), r.install_dir.join("appcache/librarycache")])
+            .filter(|p| p.is_dir())
+            .filter(|p| fs::canonicalize(p).ok() != icons_path)
+            .collect();
+
+        WatchTargets { recursive, flat, icons }
+    }
+
+    /// Returns the targets for `active` if the watcher has to be replaced after reloading it over `previous`.
+    fn after_reload(&self, previous: &Config, active: &Config, roots: &[SteamRoot]) -> Option<WatchTargets> {
+        Some(Self::find_in(active, roots)).filter(|found| found != self || previous.debounce != active.debounce)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.all().next().is_none()
+    }
+
+    fn all(&self) -> impl Iterator<Item = &PathBuf> {
+        self.recursive.iter().chain(&self.flat).chain(&self.icons)
+    }
+}
+
 impl FileChangeListener {
     /// Creates a new `FileChangeListener`.
     /// 
-    /// The `sender` should trigger a synchronization.
+    /// The `sender` should trigger a synchronization. Changes to Steam's icons and artwork only request a `SyncRequest::Icons`.
     /// Setting `run` to `false` terminates `FileChangeListener`'s internal thread, ceasing its function.
-    /// `sleep_time` controls how long the thread sleeps between checking for a change in files. Warning: This blocks shutdown requests while the thread is sleeping.
-    pub fn new(sender: mpsc::Sender<()>, run: Arc<AtomicBool>, sleep_time: Duration) -> Result<FileChangeListener, FileChangeListenerCreationError> {
-        // Currently only looks at Flatpak directory
-        let key = match env::var("HOME") {
+    /// `config` should be shared with the `Synchronizer`. With `SyncSource::InstalledApps` the `steamapps` directory of every library is watched as well, so installs and uninstalls trigger a synchronization.
+    /// The export directory is watched too, if there is one.
+    /// 
+    /// The configuration file is watched as well, provided its directory exists. Valid edits are swapped into `config`, and the watched directories follow them.
+    /// A synchronization is requested if the edit changes anything but timings. Invalid edits are logged and ignored.
+    /// Its `poll_interval` controls how long the thread sleeps between checking for a change in files. Warning: This blocks shutdown requests while the thread is sleeping.
+    pub fn new(sender: mpsc::Sender<SyncRequest>, run: Arc<AtomicBool>, config: &SharedConfig) -> Result<FileChangeListener, FileChangeListenerCreationError> {
+        let active = config.get();
+        let roots = match steam::discover_steam_roots() {
             Ok(val) => val,
             Err(_) => return Err(FileChangeListenerCreationError { kind: FileChangeListenerCreationErrorKind::NoHomeDir })
         };
+        if roots.is_empty() {
+            return Err(FileChangeListenerCreationError { kind: FileChangeListenerCreationErrorKind::NoSteamDir });
+        }
+        let targets = WatchTargets::find_in(&active, &roots);
 
-        let steam_path = Path::new(&key).join(".var/app/com.valvesoftware.Steam/data/applications");
+        // Editors usually replace files instead of writing them, so the directory is watched rather than the file.
+        // It is created up front, since a file written into a directory that appears later would go unnoticed.
+        let config_dir = config.path().and_then(Path::parent).filter(|d| match fs::create_dir_all(d) {
+            Ok(_) => true,
+            Err(e) => {
+                eprintln!("Unable to create {}, configuration changes won't be noticed: {}", d.display(), e);
+                false
+            }
+        }).map(Path::to_path_buf);
 
-        if !steam_path.is_dir() {
-            return Err(FileChangeListenerCreationError { kind: FileChangeListenerCreationErrorKind::NoSteamDir });
+        // Steam creates `userdata` on the first login, and a configuration edit may add directories, so this is only worth a note
+        if targets.is_empty() {
+            println!("Nothing to watch in the Steam installations yet");
         }
 
         let (tx, rx) = mpsc::channel();
 
-        let mut watcher = match notify::watcher(tx, Duration::from_secs(10)) {
+        let watcher = match Self::watch(&tx, &active, &targets, config_dir.as_deref()) {
             Ok(w) => w,
             Err(_) => return Err(FileChangeListenerCreationError { kind: FileChangeListenerCreationErrorKind::UnableToWatch })
         };
 
-        match watcher.watch(&steam_path, notify::RecursiveMode::Recursive) {
-            Ok(_) => {},
-            Err(_) => return Err(FileChangeListenerCreationError { kind: FileChangeListenerCreationErrorKind::UnableToWatch })
-        }
-
+        let config = config.clone();
         let thread = thread::spawn(move || {
             let mut watcher = watcher;
+            let mut targets = targets;
             loop {
                 match rx.try_recv() {
-                    Ok(_) => match sender.send(()) {
-                        Ok(()) => println!("File change detected, sync request sent."),
-                        Err(e) => eprintln!("Failed to signal synchronizer from FileChangeListener: {}", e)
+                    Ok(event) => {
+                        let path = Self::event_path(&event);
+                        if config_dir.is_some() && path.and_then(Path::parent) == config_dir.as_deref() {
+                            // The debounced event follows once the file is complete
+                            let notice = matches!(event, DebouncedEvent::NoticeWrite(_) | DebouncedEvent::NoticeRemove(_));
+                            if path == config.path() && !notice {
+                                Self::reload(&config, &sender, &tx, config_dir.as_deref(), &mut watcher, &mut targets);
+                            }
+                        } else if let Some(request) = Self::request_for(&event, &targets.icons) {
+                            match sender.send(request) {
+                                Ok(()) if request == SyncRequest::Icons => println!("Icon change detected, icon fixup request sent."),
+                                Ok(()) => println!("File change detected, sync request sent."),
+                                Err(e) => eprintln!("Failed to signal synchronizer from FileChangeListener: {}", e)
+                            }
+                        }
                     },
                     Err(e) => match e {
                         TryRecvError::Disconnected => {
@@ -362,17 +1197,19 @@ impl FileChangeListener {
                         },
                         TryRecvError::Empty => {
                             if !run.load(Ordering::SeqCst) {
-                                match watcher.unwatch(&steam_path) {
-                                    Ok(_) => println!("Unwatched steam dir"),
-                                    Err(e) => {
-                                        eprintln!("Failed to unwatch steam path: {}", e);
-                                        process::exit(5);
+                                for steam_path in targets.all().chain(&config_dir) {
+                                    match watcher.unwatch(steam_path) {
+                                        Ok(_) => println!("Unwatched steam dir {}", steam_path.display()),
+                                        Err(e) => {
+                                            eprintln!("Failed to unwatch steam path: {}", e);
+                                            process::exit(5);
+                                        }
                                     }
                                 }
 
                                 break;
                             }
-                            thread::sleep(sleep_time);
+                            thread::sleep(config.get().poll_interval());
                         }
                     }
                 }
@@ -382,6 +1219,87 @@ impl FileChangeListener {
         Ok(FileChangeListener { thread: Some(thread) })
     }
 
+    fn watch(tx: &mpsc::Sender<DebouncedEvent>, config: &Config, targets: &WatchTargets, config_dir: Option<&Path>) -> Result<RecommendedWatcher, notify::Error> {
+        let mut watcher = notify::watcher(tx.clone(), config.debounce())?;
+
+        // Manifests live directly in `steamapps`, while the subdirectories churn constantly during downloads
+        let modes = targets.recursive.iter().chain(&targets.icons).map(|p| (p, notify::RecursiveMode::Recursive))
+            .chain(targets.flat.iter().map(|p| (p, notify::RecursiveMode::NonRecursive)));
+
+        for (path, mode) in modes {
+            watcher.watch(path, mode)?;
+            println!("Watching {}", path.display());
+        }
+
+        if let Some(dir) = config_dir {
+            watcher.watch(dir, notify::RecursiveMode::NonRecursive)?;
+            println!("Watching {} for configuration changes", dir.display());
+        }
+
+        Ok(watcher)
+    }
+
+    fn reload(config: &SharedConfig, sender: &mpsc::Sender<SyncRequest>, tx: &mpsc::Sender<DebouncedEvent>, config_dir: Option<&Path>, watcher: &mut RecommendedWatcher, targets: &mut WatchTargets) {
+        let previous = match config.reload() {
+            Ok(Some(previous)) => previous,
+            Ok(None) => return,
+            Err(e) => {
+                eprintln!("Ignoring invalid configuration {}: {}", config.path().unwrap_or(Path::new("")).display(), e);
+                return;
+            }
+        };
+        let active = config.get();
+        println!("Configuration reloaded");
+
+        match steam::discover_steam_roots() {
+            Ok(roots) => if let Some(found) = targets.after_reload(&previous, &active, &roots) {
+                // Replacing the watcher drops every watch of the old one
+                match Self::watch(tx, &active, &found, config_dir) {
+                    Ok(w) => {
+                        *watcher = w;
+                        *targets = found;
+                    },
+                    Err(e) => eprintln!("Failed to watch directories of the new configuration, keeping the old ones: {}", e)
+                }
+            },
+            Err(e) => eprintln!("Failed to find directories of the new configuration, keeping the old ones: {}", e)
+        }
+
+        if previous.affects_output(&active) {
+            match sender.send(SyncRequest::Full) {
+                Ok(()) => println!("Configuration change detected, sync request sent."),
+                Err(e) => eprintln!("Failed to signal synchronizer from FileChangeListener: {}", e)
+            }
+        }
+    }
+
+    fn event_path(event: &DebouncedEvent) -> Option<&Path> {
+        match event {
+            DebouncedEvent::NoticeWrite(p) | DebouncedEvent::NoticeRemove(p) | DebouncedEvent::Create(p) | DebouncedEvent::Write(p)
+                | DebouncedEvent::Chmod(p) | DebouncedEvent::Remove(p) | DebouncedEvent::Rename(_, p) => Some(p),
+            DebouncedEvent::Rescan | DebouncedEvent::Error(_, _) => None
+        }
+    }
+
+    // A user's `config` directory is rewritten constantly while Steam runs, but only `shortcuts.vdf` matters
+    fn request_for(event: &DebouncedEvent, icon_paths: &[PathBuf]) -> Option<SyncRequest> {
+        let path = match Self::event_path(event) {
+            Some(p) => p,
+            None => return Some(SyncRequest::Full)
+        };
+
+        if icon_paths.iter().any(|p| path.starts_with(p)) {
+            return Some(SyncRequest::Icons);
+        }
+
+        let in_userdata = path.components().any(|c| c.as_os_str() == "userdata");
+        if in_userdata && path.file_name().map(|n| n != "shortcuts.vdf").unwrap_or(true) {
+            return None;
+        }
+
+        Some(SyncRequest::Full)
+    }
+
     /// Waits for the `FileChangeListener`'s internal thread to join.
     pub fn join(&mut self) {
         if let Some(thread) = self.thread.take() {
@@ -417,8 +1335,9 @@ impl SocketListener {
     /// 
     /// The `sender` should trigger a synchronization.
     /// Setting `run` to `false` terminates `SocketListener`'s internal thread, ceasing its function.
-    /// `sleep_time` controls how long the thread sleeps between checking for a socket write. Warning: This blocks shutdown requests while the thread is sleeping.
-    pub fn new(sender: mpsc::Sender<()>, run: Arc<AtomicBool>, sleep_time: Duration) -> Result<SocketListener, SocketListenerCreationError> {
+    /// `config` should be shared with the `Synchronizer`, and is used to answer dry run requests.
+    /// Its `poll_interval` controls how long the thread sleeps between checking for a socket write. Warning: This blocks shutdown requests while the thread is sleeping.
+    pub fn new(sender: mpsc::Sender<SyncRequest>, run: Arc<AtomicBool>, config: &SharedConfig) -> Result<SocketListener, SocketListenerCreationError> {
         // Attempt to load env var
         let key = match env::var("XDG_RUNTIME_DIR") {
             Ok(val) => val,
@@ -436,15 +1355,13 @@ impl SocketListener {
             }
         };
 
-        match listener.set_nonblocking(true) {
-            Err(e) => {
-                eprintln!("Failed to set listener to non-blocking: {}", e);
-                process::exit(5);
-            }
-            Ok(()) => {}
+        if let Err(e) = listener.set_nonblocking(true) {
+            eprintln!("Failed to set listener to non-blocking: {}", e);
+            process::exit(5);
         }
 
         // Create thread and wait for socket commands
+        let config = config.clone();
         let thread = thread::spawn(move || {
             for stream in listener.incoming() {
                 match stream {
@@ -456,10 +1373,21 @@ impl SocketListener {
                         }
 
                         if buf == "RUN_SYNC" {
-                            match sender.send(()) {
+                            match sender.send(SyncRequest::Full) {
                                 Ok(()) => println!("Manual sync request received, sync request sent."),
                                 Err(e) => eprintln!("Failed to signal synchronizer: {}", e)
                             }
+                        } else if buf == "DRY_RUN" {
+                            println!("Dry run request received");
+                            let response = match Synchronizer::plan(&config.get()) {
+                                Some(plan) => plan.to_string(),
+                                None => "Unable to plan synchronization\n".to_string()
+                            };
+
+                            match stream.write_all(response.as_bytes()) {
+                                Ok(_) => {}
+                                Err(e) => eprintln!("Failed to send plan: {}", e)
+                            }
                         }
                     },
                     Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
@@ -473,7 +1401,7 @@ impl SocketListener {
                             }
                             break;
                         }
-                        thread::sleep(sleep_time);
+                        thread::sleep(config.get().poll_interval());
                     }
                     Err(e) => eprintln!("Failed to get stream: {}", e)
                 }
@@ -489,4 +1417,290 @@ impl SocketListener {
             thread.join().expect("Unable to join thread");
         }
     }
-}
\ No newline at end of file
+}
+
+/// Learns the window classes of running games while `learn_wm_classes` is enabled, and triggers a synchronization when it learns a new one.
+pub struct WindowClassLearner {
+    thread: Option<thread::JoinHandle<()>>
+}
+
+impl WindowClassLearner {
+    /// Creates a new `WindowClassLearner`.
+    ///
+    /// The `sender` should trigger a synchronization.
+    /// Setting `run` to `false` terminates `WindowClassLearner`'s internal thread, ceasing its function.
+    /// `config` should be shared with the `Synchronizer`. Nothing is learned while its `learn_wm_classes` is disabled.
+    /// Its `poll_interval` controls how long the thread sleeps between looking at windows. Warning: This blocks shutdown requests while the thread is sleeping.
+    /// Classes that can't be loaded are relearned from scratch.
+    pub fn new(sender: mpsc::Sender<SyncRequest>, run: Arc<AtomicBool>, config: &SharedConfig) -> WindowClassLearner {
+        let mut learned = LearnedClasses::load_or_empty();
+
+        let config = config.clone();
+        let thread = thread::spawn(move || {
+            let mut seen = HashSet::new();
+            // Without an X server this fails every time, which is only worth saying once
+            let mut reported = false;
+
+            while run.load(Ordering::SeqCst) {
+                let active = config.get();
+                if active.learn_wm_classes {
+                    match wm_class::observe_windows(&mut seen) {
+                        Ok(observed) => {
+                            let mut changed = false;
+                            for (id, class) in observed {
+                                if learned.learn(&id, &class) {
+                                    println!("Learned window class {} of app {}", class, id);
+                                    changed = true;
+                                }
+                            }
+
+                            if changed {
+                                match learned.save() {
+                                    Ok(_) => {}
+                                    Err(e) => eprintln!("Unable to save learned window classes: {}", e)
+                                }
+                                match sender.send(SyncRequest::Full) {
+                                    Ok(()) => println!("Window class learned, sync request sent."),
+                                    Err(e) => eprintln!("Failed to signal synchronizer from WindowClassLearner: {}", e)
+                                }
+                            }
+                            reported = false;
+                        },
+                        Err(e) if !reported => {
+                            eprintln!("Unable to look at windows: {}", e);
+                            reported = true;
+                        },
+                        Err(_) => {}
+                    }
+                }
+
+                thread::sleep(active.poll_interval());
+            }
+        });
+
+        WindowClassLearner { thread: Some(thread) }
+    }
+
+    /// Waits for the `WindowClassLearner`'s internal thread to join.
+    pub fn join(&mut self) {
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("Unable to join thread");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_home(name: &str) -> PathBuf {
+        let home = env::temp_dir().join(format!("steam-shortcut-sync-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(&home).unwrap();
+
+        fs::canonicalize(home).unwrap()
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        [&b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR"[..], &width.to_be_bytes(), &height.to_be_bytes()].concat()
+    }
+
+    #[test]
+    fn indexes_theme_icons_largest_first() {
+        let home = temp_home("theme");
+        for (dir, size) in [("64x64", 64), ("256x256", 256), ("32x32", 32), ("legacy", 32)] {
+            fs::create_dir_all(home.join(dir).join("apps")).unwrap();
+            fs::write(home.join(dir).join("apps/steam_icon_10.png"), png(size, size)).unwrap();
+        }
+        fs::write(home.join("64x64/apps/steam_icon_20.png"), png(64, 64)).unwrap();
+        fs::write(home.join("64x64/apps/other.png"), png(64, 64)).unwrap();
+
+        let index = Synchronizer::index_theme_icons(&home);
+        assert_eq!(index.len(), 2);
+        assert_eq!(index["10"], [
+            (256, 256, home.join("256x256/apps/steam_icon_10.png")),
+            (64, 64, home.join("64x64/apps/steam_icon_10.png")),
+            (32, 32, home.join("32x32/apps/steam_icon_10.png")),
+        ]);
+        assert_eq!(index["20"], [(64, 64, home.join("64x64/apps/steam_icon_20.png"))]);
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn reuses_icons_made_from_the_same_artwork() {
+        let home = temp_home("reuse-icons");
+        let icons_path = home.join("icons");
+        let art = home.join("header.jpg");
+        let installed = icons_path.join("32x32/apps/steam_icon_10.png");
+        fs::create_dir_all(installed.parent().unwrap()).unwrap();
+        // Rendering this would fail, so any icon returned must be the installed one
+        fs::write(&art, b"artwork").unwrap();
+        fs::write(&installed, png(32, 32)).unwrap();
+
+        let mut state = SyncState::load_from(&home.join("state")).unwrap();
+        state.record_converted(&installed, ArtifactKind::Icon, "10", &png(32, 32), Some(&state::hash_contents(b"artwork")));
+
+        let desktop = SteamDesktopFile::new("Game".to_string(), "10".to_string());
+        let source = IconSource { theme: home.join("theme"), artwork: vec![art.clone()], file: None };
+        let load = |state: &SyncState| Synchronizer::load_icons(&desktop, &ThemeIcons::new(), &source, &Config::default(), state, &icons_path);
+
+        let icons = load(&state);
+        assert_eq!(icons.iter().map(|i| (i.path.clone(), i.contents.clone())).collect::<Vec<_>>(), [(PathBuf::from("32x32/apps/steam_icon_10.png"), png(32, 32))]);
+        assert_eq!(icons[0].source_hash, Some(state::hash_contents(b"artwork")));
+
+        // New artwork or an edited icon means rendering again
+        fs::write(&art, b"new artwork").unwrap();
+        assert!(load(&state).is_empty());
+        fs::write(&art, b"artwork").unwrap();
+        fs::write(&installed, b"edited").unwrap();
+        assert!(load(&state).is_empty());
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn watches_userdata_of_native_steam() {
+        let home = temp_home("watch-native");
+        let data = home.join(".local/share");
+        fs::create_dir_all(data.join("Steam/userdata/1/config")).unwrap();
+        fs::create_dir_all(data.join("applications")).unwrap();
+        let config = Config { applications_dir: Some(data.join("applications")), ..Default::default() };
+
+        // Native Steam writes into the destination, which leaves `userdata` as the only thing to watch
+        let targets = WatchTargets::find_in(&config, &steam::discover_steam_roots_in(&home));
+        assert_eq!(targets.recursive, [data.join("Steam/userdata")]);
+        assert!(targets.flat.is_empty());
+        assert!(!targets.is_empty());
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn renames_owned_files_to_their_stable_name() {
+        let home = temp_home("migrate");
+        let applications = home.join("applications");
+        fs::create_dir_all(&applications).unwrap();
+
+        let entry = |name: &str, id: u32| format!("[Desktop Entry]\nName={}\nType=Application\nExec=steam steam://rungameid/{}", name, id);
+        let mut state = SyncState::load_from(&home.join("state")).unwrap();
+        for (file, id, owned) in [("Game A.desktop", 10, true), ("Game B.desktop", 20, true), ("Game C.desktop", 30, false)] {
+            let contents = entry(file, id);
+            fs::write(applications.join(file), &contents).unwrap();
+            if owned {
+                state.record(&applications.join(file), ArtifactKind::DesktopFile, &id.to_string(), contents.as_bytes());
+            }
+        }
+        fs::write(applications.join("steam-app-20.desktop"), "taken").unwrap();
+
+        let mut indexed = Synchronizer::load_desktop_files(&applications);
+        Synchronizer::migrate_file_names(&mut indexed, &applications, &mut state);
+
+        let renamed = applications.join("steam-app-10.desktop");
+        assert!(renamed.is_file() && !applications.join("Game A.desktop").exists());
+        assert!(indexed.iter().any(|d| d.path.as_ref() == Some(&renamed)));
+        assert_eq!(state.ownership(&renamed), Ownership::Owned);
+        state.save().unwrap();
+        let saved = SyncState::load_from(&home.join("state")).unwrap();
+        assert_eq!(saved.ownership(&renamed), Ownership::Owned);
+        assert!(saved.get(&applications.join("Game A.desktop")).is_none());
+
+        // An existing destination is never overwritten, and files the daemon didn't write stay where they are
+        assert_eq!(fs::read_to_string(applications.join("steam-app-20.desktop")).unwrap(), "taken");
+        assert!(state.get(&applications.join("Game B.desktop")).is_some());
+        assert!(applications.join("Game C.desktop").is_file() && !applications.join("steam-app-30.desktop").exists());
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn rebuilds_watch_targets_after_a_reload() {
+        let home = temp_home("watch-reload");
+        let data = home.join(".local/share");
+        fs::create_dir_all(data.join("Steam/userdata")).unwrap();
+        fs::create_dir_all(home.join("export")).unwrap();
+        let roots = steam::discover_steam_roots_in(&home);
+        let path = home.join("config.toml");
+        let applications = format!("applications_dir = \"{}\"\n", data.join("applications").display());
+        fs::write(&path, &applications).unwrap();
+
+        let config = SharedConfig::new(Config::load_from(&path).unwrap(), Some(path.clone()), Default::default());
+        let targets = WatchTargets::find_in(&config.get(), &roots);
+
+        // Settings the watcher doesn't depend on keep it
+        fs::write(&path, format!("{}poll_interval = 7", applications)).unwrap();
+        let previous = config.reload().unwrap().unwrap();
+        assert_eq!(targets.after_reload(&previous, &config.get(), &roots), None);
+
+        fs::write(&path, format!("{}export_dir = \"{}\"", applications, home.join("export").display())).unwrap();
+        let previous = config.reload().unwrap().unwrap();
+        let found = targets.after_reload(&previous, &config.get(), &roots).unwrap();
+        assert_eq!(found.recursive, [data.join("Steam/userdata"), home.join("export")]);
+
+        fs::write(&path, format!("{}export_dir = \"{}\"\ndebounce = 2", applications, home.join("export").display())).unwrap();
+        let previous = config.reload().unwrap().unwrap();
+        assert_eq!(found.after_reload(&previous, &config.get(), &roots), Some(found.clone()));
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn never_watches_the_destinations() {
+        let home = temp_home("watch-destinations");
+        let data = home.join(".local/share");
+        fs::create_dir_all(data.join("Steam/appcache/librarycache")).unwrap();
+        fs::create_dir_all(data.join("icons/hicolor")).unwrap();
+        fs::create_dir_all(data.join("applications")).unwrap();
+        let roots = steam::discover_steam_roots_in(&home);
+
+        let config = Config { applications_dir: Some(data.join("applications")), icons_dir: Some(data.join("icons/hicolor")), ..Default::default() };
+        assert_eq!(WatchTargets::find_in(&config, &roots).icons, [data.join("Steam/appcache/librarycache")]);
+
+        // Installing icons somewhere else leaves Steam's own to watch
+        let config = Config { icons_dir: Some(home.join("icons")), ..config };
+        assert_eq!(WatchTargets::find_in(&config, &roots).icons, [data.join("icons/hicolor"), data.join("Steam/appcache/librarycache")]);
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn requests_what_an_event_needs() {
+        let icons = [PathBuf::from("/steam/icons/hicolor"), PathBuf::from("/steam/appcache/librarycache")];
+        let request = |event| FileChangeListener::request_for(&event, &icons);
+
+        assert_eq!(request(DebouncedEvent::Create(PathBuf::from("/steam/icons/hicolor/32x32/apps/steam_icon_10.png"))), Some(SyncRequest::Icons));
+        assert_eq!(request(DebouncedEvent::Write(PathBuf::from("/steam/appcache/librarycache/10_header.jpg"))), Some(SyncRequest::Icons));
+        assert_eq!(request(DebouncedEvent::Create(PathBuf::from("/steam/applications/Game.desktop"))), Some(SyncRequest::Full));
+        assert_eq!(request(DebouncedEvent::Rename(PathBuf::from("/tmp/x"), PathBuf::from("/steam/userdata/1/config/shortcuts.vdf"))), Some(SyncRequest::Full));
+        assert_eq!(request(DebouncedEvent::Write(PathBuf::from("/steam/userdata/1/config/localconfig.vdf"))), None);
+        assert_eq!(request(DebouncedEvent::Rescan), Some(SyncRequest::Full));
+    }
+
+    #[test]
+    fn adopts_only_unchanged_legacy_files() {
+        let home = temp_home("legacy");
+        let applications = home.join("applications");
+        let icons = home.join(".icons");
+        fs::create_dir_all(&applications).unwrap();
+        fs::create_dir_all(&icons).unwrap();
+
+        let legacy = |name: &str, id: u32| format!("[Desktop Entry]\nName={}\nIcon=steam_icon_{}\nType=Application\nExec=xdg-open steam://rungameid/{}", name, id, id);
+        fs::write(applications.join("steam_app_10.desktop"), legacy("Game", 10)).unwrap();
+        fs::write(icons.join("steam_icon_10.png"), b"png").unwrap();
+        fs::write(applications.join("steam_app_20.desktop"), legacy("Edited", 20) + "\nComment=Mine").unwrap();
+        fs::write(icons.join("steam_icon_20.png"), b"png").unwrap();
+        fs::write(applications.join("my-game.desktop"), "[Desktop Entry]\nName=Mine\nType=Application\nExec=steam steam://rungameid/30").unwrap();
+
+        let mut state = SyncState::load_from(&home.join("state")).unwrap();
+        Synchronizer::adopt_legacy_files(&mut state, &Synchronizer::load_desktop_files(&applications), Some(&icons));
+
+        let owned = state.iter().map(|(p, a)| (p.to_path_buf(), a.kind, a.app_id.clone())).collect::<Vec<_>>();
+        assert_eq!(owned, [
+            (icons.join("steam_icon_10.png"), ArtifactKind::Icon, "10".to_string()),
+            (applications.join("steam_app_10.desktop"), ArtifactKind::DesktopFile, "10".to_string()),
+        ]);
+        assert_eq!(state.ownership(&applications.join("my-game.desktop")), Ownership::Foreign);
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
@@ -0,0 +1,279 @@
+//! Reads Steam's library folders and the app manifests inside them to find out which games are actually installed.
+
+use std::{fs, io, path::{Path, PathBuf}};
+
+use crate::vdf::{self, VdfObject, VdfParseError};
+
+/// `StateFlags` bit set once an app has finished installing.
+pub const STATE_FULLY_INSTALLED: u32 = 4;
+
+/// A Steam library folder and the apps installed in it.
+#[derive(Debug, Clone)]
+pub struct SteamLibrary {
+    /// The library's root directory, containing `steamapps`.
+    pub path: PathBuf,
+    /// The user-facing label of the library, which is often empty.
+    pub label: String,
+    /// Every app with a readable manifest in this library.
+    pub apps: Vec<InstalledApp>,
+}
+
+/// An app described by an `appmanifest_<id>.acf` file.
+#[derive(Debug, Clone)]
+pub struct InstalledApp {
+    /// The Steam app id.
+    pub appid: u32,
+    /// The display name Steam shows for the app.
+    pub name: String,
+    /// The directory name of the app below `steamapps/common`.
+    pub installdir: String,
+    /// The installed size in bytes.
+    pub size_on_disk: u64,
+    /// Steam's install state bit flags.
+    pub state_flags: u32,
+    /// The root directory of the library the app is installed in.
+    pub library: PathBuf,
+}
+
+impl InstalledApp {
+    /// Returns `true` if Steam considers the app completely installed.
+    pub fn is_fully_installed(&self) -> bool {
+        self.state_flags & STATE_FULLY_INSTALLED != 0
+    }
+
+    /// The absolute path of the app's install directory.
+    pub fn install_path(&self) -> PathBuf {
+        self.library.join("steamapps/common").join(&self.installdir)
+    }
+}
+
+/// Possible errors when loading Steam libraries.
+#[derive(Debug)]
+pub struct LibraryLoadError {
+    /// The specific kind of error.
+    pub kind: LibraryLoadErrorKind
+}
+
+#[derive(Debug)]
+pub enum LibraryLoadErrorKind {
+    /// A file could not be read.
+    Io(io::Error),
+    /// A file was not valid KeyValues.
+    Parse(VdfParseError),
+    /// A file was valid KeyValues but was missing required keys.
+    MissingKey(&'static str),
+}
+
+impl From<io::Error> for LibraryLoadError {
+    fn from(e: io::Error) -> Self {
+        LibraryLoadError { kind: LibraryLoadErrorKind::Io(e) }
+    }
+}
+
+impl From<VdfParseError> for LibraryLoadError {
+    fn from(e: VdfParseError) -> Self {
+        LibraryLoadError { kind: LibraryLoadErrorKind::Parse(e) }
+    }
+}
+
+/// Loads every library folder of the Steam installation at `install_dir` along with its app manifests.
+///
+/// If `steamapps/libraryfolders.vdf` doesn't exist, `install_dir` is treated as the only library.
+/// Manifests that can't be read are skipped.
+pub fn load_libraries(install_dir: &Path) -> Result<Vec<SteamLibrary>, LibraryLoadError> {
+    let folders_path = install_dir.join("steamapps/libraryfolders.vdf");
+
+    let mut folders = if folders_path.is_file() {
+        parse_library_folders(&fs::read_to_string(&folders_path)?)?
+    } else {
+        Vec::new()
+    };
+
+    if !folders.iter().any(|(path, _)| same_dir(path, install_dir)) {
+        folders.insert(0, (install_dir.to_path_buf(), String::new()));
+    }
+
+    Ok(folders.into_iter()
+        .filter(|(path, _)| path.join("steamapps").is_dir())
+        .map(|(path, label)| {
+            let apps = load_manifests(&path);
+            SteamLibrary { path, label, apps }
+        })
+        .collect())
+}
+
+/// Returns every installed app across all of the libraries of the Steam installation at `install_dir`.
+pub fn installed_apps(install_dir: &Path) -> Result<Vec<InstalledApp>, LibraryLoadError> {
+    Ok(load_libraries(install_dir)?
+        .into_iter()
+        .flat_map(|l| l.apps)
+        .collect())
+}
+
+/// Parses an app manifest file's contents.
+pub fn parse_app_manifest(contents: &str, library: &Path) -> Result<InstalledApp, LibraryLoadError> {
+    let doc = vdf::parse(contents)?;
+    let state = doc.get_object("AppState").ok_or(LibraryLoadError { kind: LibraryLoadErrorKind::MissingKey("AppState") })?;
+
+    let appid = state.get_str("appid")
+        .and_then(|s| s.parse().ok())
+        .ok_or(LibraryLoadError { kind: LibraryLoadErrorKind::MissingKey("appid") })?;
+
+    Ok(InstalledApp {
+        appid,
+        name: state.get_str("name").unwrap_or_default().to_string(),
+        installdir: state.get_str("installdir").unwrap_or_default().to_string(),
+        size_on_disk: state.get_str("SizeOnDisk").and_then(|s| s.parse().ok()).unwrap_or(0),
+        state_flags: state.get_str("StateFlags").and_then(|s| s.parse().ok()).unwrap_or(0),
+        library: library.to_path_buf(),
+    })
+}
+
+fn parse_library_folders(contents: &str) -> Result<Vec<(PathBuf, String)>, LibraryLoadError> {
+    let doc = vdf::parse(contents)?;
+    // Older clients used `LibraryFolders` as the root key
+    let root = doc.get_object("libraryfolders").ok_or(LibraryLoadError { kind: LibraryLoadErrorKind::MissingKey("libraryfolders") })?;
+
+    Ok(root.iter()
+        .filter(|(key, _)| key.parse::<u32>().is_ok())
+        .filter_map(|(_, value)| match value {
+            // Older clients stored only the path
+            vdf::VdfValue::String(path) => Some((PathBuf::from(path), String::new())),
+            vdf::VdfValue::Object(folder) => folder_entry(folder)
+        })
+        .collect())
+}
+
+fn folder_entry(folder: &VdfObject) -> Option<(PathBuf, String)> {
+    let path = folder.get_str("path")?;
+
+    Some((PathBuf::from(path), folder.get_str("label").unwrap_or_default().to_string()))
+}
+
+fn load_manifests(library: &Path) -> Vec<InstalledApp> {
+    let entries = match fs::read_dir(library.join("steamapps")) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("Unable to read library {}: {}", library.display(), e);
+            return Vec::new();
+        }
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .map(|e| e.path())
+        .filter(|p| {
+            let name = p.file_name().and_then(|n| n.to_str()).unwrap_or("");
+            name.starts_with("appmanifest_") && name.ends_with(".acf")
+        })
+        .filter_map(|p| {
+            let contents = match fs::read_to_string(&p) {
+                Ok(c) => c,
+                Err(e) => {
+                    eprintln!("Unable to read app manifest {}: {}", p.display(), e);
+                    return None;
+                }
+            };
+
+            match parse_app_manifest(&contents, library) {
+                Ok(app) => Some(app),
+                Err(e) => {
+                    eprintln!("Unable to parse app manifest {}: {:?}", p.display(), e.kind);
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+fn same_dir(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    const LIBRARY_FOLDERS: &str = r#""libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"apps"
+		{
+			"228980"		"0"
+		}
+	}
+	"1"
+	{
+		"path"		"/mnt/games/SteamLibrary"
+		"label"		"Games"
+	}
+	"contentstatsid"		"-123"
+}"#;
+
+    fn manifest(appid: u32, state_flags: u32) -> String {
+        format!("\"AppState\"\n{{\n\t\"appid\"\t\t\"{}\"\n\t\"name\"\t\t\"Game {}\"\n\t\"StateFlags\"\t\t\"{}\"\n\t\"installdir\"\t\t\"Game{}\"\n\t\"SizeOnDisk\"\t\t\"1024\"\n}}\n", appid, appid, state_flags, appid)
+    }
+
+    #[test]
+    fn parses_library_folders() {
+        assert_eq!(parse_library_folders(LIBRARY_FOLDERS).unwrap(), [
+            (PathBuf::from("/home/user/.local/share/Steam"), String::new()),
+            (PathBuf::from("/mnt/games/SteamLibrary"), "Games".to_string()),
+        ]);
+
+        // Older clients stored only the path
+        let old = "\"libraryfolders\"\n{\n\t\"TimeNextStatsReport\"\t\t\"1\"\n\t\"1\"\t\t\"/mnt/old\"\n}";
+        assert_eq!(parse_library_folders(old).unwrap(), [(PathBuf::from("/mnt/old"), String::new())]);
+
+        assert!(matches!(parse_library_folders("\"other\" {}").unwrap_err().kind, LibraryLoadErrorKind::MissingKey("libraryfolders")));
+    }
+
+    #[test]
+    fn parses_app_manifests() {
+        let app = parse_app_manifest(&manifest(620, 4), Path::new("/mnt/games")).unwrap();
+        assert_eq!(app.appid, 620);
+        assert_eq!(app.name, "Game 620");
+        assert_eq!(app.size_on_disk, 1024);
+        assert_eq!(app.install_path(), Path::new("/mnt/games/steamapps/common/Game620"));
+        assert!(app.is_fully_installed());
+
+        // An update that is pending keeps the game playable, while one that started doesn't
+        assert!(parse_app_manifest(&manifest(620, 6), Path::new("/")).unwrap().is_fully_installed());
+        assert!(!parse_app_manifest(&manifest(620, 1026), Path::new("/")).unwrap().is_fully_installed());
+        assert!(!parse_app_manifest(&manifest(620, 0), Path::new("/")).unwrap().is_fully_installed());
+
+        assert!(matches!(parse_app_manifest("\"AppState\" { \"name\" \"x\" }", Path::new("/")).unwrap_err().kind, LibraryLoadErrorKind::MissingKey("appid")));
+    }
+
+    #[test]
+    fn loads_libraries_with_steamapps() {
+        let dir = env::temp_dir().join(format!("steam-shortcut-sync-library-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let steam = dir.join("Steam");
+        let other = dir.join("Other");
+        fs::create_dir_all(steam.join("steamapps")).unwrap();
+        fs::create_dir_all(other.join("steamapps")).unwrap();
+        fs::create_dir_all(dir.join("Unmounted")).unwrap();
+
+        let folders = format!("\"libraryfolders\" {{ \"0\" {{ \"path\" \"{}\" }} \"1\" {{ \"path\" \"{}\" }} }}", other.display(), dir.join("Unmounted").display());
+        fs::write(steam.join("steamapps/libraryfolders.vdf"), folders).unwrap();
+        fs::write(steam.join("steamapps/appmanifest_10.acf"), manifest(10, 4)).unwrap();
+        fs::write(other.join("steamapps/appmanifest_20.acf"), manifest(20, 4)).unwrap();
+        fs::write(other.join("steamapps/appmanifest_30.acf"), "broken {").unwrap();
+
+        // The Steam directory is a library even when the file doesn't list it, and a library without `steamapps` is skipped
+        let libraries = load_libraries(&steam).unwrap();
+        assert_eq!(libraries.iter().map(|l| l.path.clone()).collect::<Vec<_>>(), [steam.clone(), other.clone()]);
+        assert_eq!(installed_apps(&steam).unwrap().iter().map(|a| a.appid).collect::<Vec<_>>(), [10, 20]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
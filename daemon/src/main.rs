@@ -1,6 +1,6 @@
-use std::{sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc}, process, time::Duration};
+use std::{sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc}, process, env, path::PathBuf};
 
-use steam_shortcut_sync::{Synchronizer, FileChangeListener, SocketListener};
+use steam_shortcut_sync::{Synchronizer, FileChangeListener, SocketListener, WindowClassLearner, SyncSource, SyncRequest, config::{self, Config, Overrides, SharedConfig}, doctor, url_handler::{self, HandlerRecord}};
 
 fn main() {
     println!("Steam Shortcut Sync v.{}", option_env!("CARGO_PKG_VERSION").unwrap_or("UNKNOWN"));
@@ -20,9 +20,86 @@ fn main() {
         }
     }
 
+    // Arguments take precedence over the configuration file
+    let mut config_path = None;
+    let mut overrides = Overrides::default();
+    let mut run_doctor = false;
+    let mut unregister = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            // Opt-in to launchers for every installed game instead of only Steam's shortcuts
+            "--all-installed" => overrides.source = Some(SyncSource::InstalledApps),
+            // Report what launchers depend on instead of starting
+            "--doctor" => run_doctor = true,
+            // Undo the steam:// handler registration instead of starting
+            "--unregister-url-handler" => unregister = true,
+            "--export-dir" | "--config" => match args.next() {
+                Some(path) if arg == "--config" => config_path = Some(PathBuf::from(path)),
+                // Made absolute here, since reloads validate the overrides and require absolute paths
+                Some(dir) => overrides.export_dir = Some(env::current_dir().map(|d| d.join(&dir)).unwrap_or_else(|_| PathBuf::from(dir))),
+                None => {
+                    eprintln!("{} requires a path", arg);
+                    process::exit(9);
+                }
+            },
+            _ => {
+                eprintln!("Unknown argument {}", arg);
+                process::exit(9);
+            }
+        }
+    }
+
+    let loaded = match &config_path {
+        Some(path) => Config::load_from(path),
+        None => Config::load()
+    };
+    let config_path = config_path.or_else(|| config::config_path().ok());
+    let mut config = match loaded {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("Invalid configuration {}: {}", config_path.unwrap_or_default().display(), e);
+            process::exit(10);
+        }
+    };
+    overrides.apply(&mut config);
+    if let Err(e) = config.validate() {
+        eprintln!("Invalid arguments: {}", e);
+        process::exit(9);
+    }
+
+    if run_doctor {
+        let checks = doctor::run(&config);
+        for check in &checks {
+            println!("{}", check);
+        }
+        process::exit(if checks.iter().all(|c| c.passed) { 0 } else { 12 });
+    }
+
+    if unregister {
+        let undone = HandlerRecord::load()
+            .map_err(|e| format!("{:?}", e.kind))
+            .and_then(|mut record| url_handler::unregister(&mut record).map_err(|e| e.to_string()));
+        match undone {
+            Ok(undone) if undone.is_empty() => println!("No steam:// handler was registered"),
+            Ok(undone) => undone.iter().for_each(|line| println!("{}", line)),
+            Err(e) => {
+                eprintln!("Unable to unregister the steam:// handler: {}", e);
+                process::exit(13);
+            }
+        }
+        if config.register_url_handler && config.uses_url_handler() {
+            println!("Set register_url_handler = false, or the daemon registers it again when needed");
+        }
+        process::exit(0);
+    }
+
+    // Edits to the file are picked up while running, with the arguments still applied on top
+    let config = SharedConfig::new(config, config_path, overrides);
+
     let (sender, receiver) = mpsc::channel();
 
-    let mut sync = match Synchronizer::new(receiver, Arc::clone(&run), Duration::from_secs(5)) {
+    let mut sync = match Synchronizer::new(receiver, Arc::clone(&run), config.clone()) {
         Ok(s) => s,
         Err(_) => {
             eprintln!("Error creating synchronizer!");
@@ -30,7 +107,7 @@ fn main() {
         }
     };
 
-    let mut file_watcher = match FileChangeListener::new(sender.clone(), Arc::clone(&run), Duration::from_secs(5)) {
+    let mut file_watcher = match FileChangeListener::new(sender.clone(), Arc::clone(&run), &config) {
         Ok(f) => f,
         Err(_) => {
             eprintln!("Error creating file watcher!");
@@ -38,7 +115,7 @@ fn main() {
         }
     };
 
-    let mut socket_watcher = match SocketListener::new(sender.clone(), Arc::clone(&run), Duration::from_secs(5)) {
+    let mut socket_watcher = match SocketListener::new(sender.clone(), Arc::clone(&run), &config) {
         Ok(s) => s,
         Err(_) => {
             eprintln!("Error creating socket watcher!");
@@ -46,13 +123,16 @@ fn main() {
         }
     };
 
+    let mut class_learner = WindowClassLearner::new(sender.clone(), Arc::clone(&run), &config);
+
     println!("Startup Complete");
 
     // Initial run on startup
-    sender.send(()).unwrap();
+    sender.send(SyncRequest::Full).unwrap();
 
     file_watcher.join();
     socket_watcher.join();
+    class_learner.join();
     sync.join();
 
     println!("Shutdown Complete");
@@ -0,0 +1,564 @@
+//! Planning of the changes a synchronization makes.
+//!
+//! A `SyncPlan` is computed from the launchers that should exist and a snapshot of what does exist, without touching the disk.
+//! That way it can be reviewed before anything is changed, and applied separately.
+
+use std::{collections::{HashMap, HashSet}, fmt, fs, io, path::{Path, PathBuf}, process::Command};
+
+use crate::{config::Config, state::{self, ArtifactKind, Ownership, SyncState}};
+
+/// A launcher that should exist.
+#[derive(Debug, Clone)]
+pub struct SourceEntry {
+    /// The Steam app id, or game id for non-Steam shortcuts.
+    pub app_id: String,
+    /// The display name.
+    pub name: String,
+    /// The file name the launcher gets when it is created.
+    pub file_name: String,
+    /// The complete contents of the `.desktop` file.
+    pub contents: String,
+    /// The icons that are installed alongside it, one for each size.
+    pub icons: Vec<SourceIcon>,
+}
+
+/// An app that doesn't get a launcher because a filter rule excludes it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExcludedEntry {
+    /// The Steam app id, or game id for non-Steam shortcuts.
+    pub app_id: String,
+    /// The display name.
+    pub name: String,
+    /// The rule that excludes it.
+    pub reason: String,
+}
+
+/// An icon that should be installed for a launcher.
+#[derive(Debug, Clone)]
+pub struct SourceIcon {
+    /// The image the icon was made from.
+    pub src: PathBuf,
+    /// Where the icon is installed, relative to the icon theme directory.
+    pub path: PathBuf,
+    /// The PNG that is installed.
+    pub contents: Vec<u8>,
+    /// The SHA-256 of `contents`, as returned by `state::hash_contents`.
+    pub hash: String,
+    /// The SHA-256 of `src`, if the icon was converted from it rather than copied.
+    pub source_hash: Option<String>,
+}
+
+/// A launcher for a Steam game that exists in the applications directory.
+#[derive(Debug, Clone)]
+pub struct ExistingEntry {
+    /// The app id it launches.
+    pub app_id: String,
+    /// The display name.
+    pub name: String,
+    /// Where the file is.
+    pub path: PathBuf,
+    /// The contents of the file.
+    pub contents: String,
+    /// Whether the daemon may change or remove it.
+    pub ownership: Ownership,
+}
+
+/// Any other file a plan may replace or remove.
+#[derive(Debug, Clone)]
+pub struct ExistingFile {
+    /// The SHA-256 of the contents, as returned by `state::hash_contents`.
+    pub hash: String,
+    /// Whether the daemon may change or remove it.
+    pub ownership: Ownership,
+}
+
+/// A snapshot of everything in the destination that a plan depends on.
+#[derive(Debug, Clone, Default)]
+pub struct Destination {
+    /// The directory launchers are created in.
+    pub applications_dir: PathBuf,
+    /// The icon theme directory icons are installed into.
+    pub icons_dir: PathBuf,
+    /// Every launcher that starts a Steam game.
+    pub entries: Vec<ExistingEntry>,
+    /// Other files that exist where the plan may write or remove, by path.
+    pub files: HashMap<PathBuf, ExistingFile>,
+    /// The icons the daemon installed for each app, by app id.
+    pub icons: HashMap<String, Vec<PathBuf>>,
+}
+
+/// A single change to the disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncAction {
+    /// Writes a new launcher.
+    CreateEntry { app_id: String, name: String, path: PathBuf, contents: String },
+    /// Rewrites an existing launcher in place.
+    UpdateEntry { app_id: String, name: String, path: PathBuf, contents: String },
+    /// Removes a launcher.
+    RemoveEntry { app_id: String, name: String, path: PathBuf },
+    /// Installs an icon made from `src`, replacing an older copy.
+    CopyIcon { app_id: String, src: PathBuf, dest: PathBuf, contents: Vec<u8>, source_hash: Option<String> },
+    /// Removes an installed icon.
+    RemoveIcon { app_id: String, path: PathBuf },
+    /// Runs `update-desktop-database` on a directory.
+    RefreshDatabase { dir: PathBuf },
+    /// Runs `gtk-update-icon-cache` on an icon theme directory.
+    RefreshIconCache { dir: PathBuf },
+}
+
+/// An action and why it is taken.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlannedAction {
+    pub action: SyncAction,
+    pub reason: String,
+}
+
+/// A launcher that is left alone even though it differs from Steam, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Everything a synchronization changes, in the order it is applied.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SyncPlan {
+    pub actions: Vec<PlannedAction>,
+    pub skipped: Vec<SkippedEntry>,
+    pub excluded: Vec<ExcludedEntry>,
+}
+
+impl SyncPlan {
+    /// Works out how to get from `destination` to a state where every launcher in `sources` exists.
+    ///
+    /// Launchers are matched by app id, so renamed games are updated in place.
+    /// Only files the daemon wrote and nobody modified since are changed or removed, and only if `config` allows removing launchers.
+    /// Launchers of `excluded` apps are removed like those of apps that are gone, giving the rule as the reason.
+    pub fn compute(sources: &[SourceEntry], excluded: &[ExcludedEntry], destination: &Destination, config: &Config) -> SyncPlan {
+        let mut plan = SyncPlan { excluded: excluded.to_vec(), ..Default::default() };
+        let exclusions: HashMap<&str, &str> = excluded.iter().map(|e| (e.app_id.as_str(), e.reason.as_str())).collect();
+        let source_ids: HashSet<&str> = sources.iter().map(|s| s.app_id.as_str()).collect();
+
+        for source in sources {
+            let current: Vec<&ExistingEntry> = destination.entries.iter().filter(|e| e.app_id == source.app_id).collect();
+            // A file this daemon wrote is preferred over any other launcher for the same app
+            let owned = current.iter().find(|e| e.ownership != Ownership::Foreign);
+
+            match (owned, current.first()) {
+                (Some(old), _) => {
+                    if old.contents == source.contents {
+                        // Already up to date
+                    } else if old.ownership == Ownership::Modified {
+                        plan.skip(&source.name, &old.path, "modified after it was written");
+                    } else {
+                        let reason = if old.name != source.name {
+                            format!("renamed from {}", old.name)
+                        } else {
+                            "changed in Steam".to_string()
+                        };
+                        plan.push(SyncAction::UpdateEntry {
+                            app_id: source.app_id.clone(),
+                            name: source.name.clone(),
+                            path: old.path.clone(),
+                            contents: source.contents.clone()
+                        }, reason);
+                    }
+                },
+                (None, Some(other)) => plan.skip(&source.name, &other.path, "another launcher already starts this game"),
+                (None, None) => {
+                    let path = destination.applications_dir.join(&source.file_name);
+                    match destination.files.get(&path) {
+                        Some(file) if file.ownership != Ownership::Owned => plan.skip(&source.name, &path, "a file that wasn't written by Steam Shortcut Sync is in the way"),
+                        _ => plan.push(SyncAction::CreateEntry {
+                            app_id: source.app_id.clone(),
+                            name: source.name.clone(),
+                            path,
+                            contents: source.contents.clone()
+                        }, "new in Steam".to_string())
+                    }
+                }
+            }
+
+            plan.plan_icons(source, destination);
+        }
+
+        // Whatever is left belongs to apps that are gone
+        for entry in destination.entries.iter().filter(|e| !source_ids.contains(e.app_id.as_str())) {
+            match entry.ownership {
+                Ownership::Owned if !config.remove_launchers => {
+                    plan.skip(&entry.name, &entry.path, "removing launchers is disabled");
+                    continue;
+                },
+                Ownership::Owned => {},
+                Ownership::Modified => {
+                    plan.skip(&entry.name, &entry.path, "modified after it was written");
+                    continue;
+                },
+                Ownership::Foreign => {
+                    plan.skip(&entry.name, &entry.path, "not written by Steam Shortcut Sync");
+                    continue;
+                }
+            }
+
+            let reason = match exclusions.get(entry.app_id.as_str()) {
+                Some(rule) => format!("excluded by {}", rule),
+                None => "no longer in Steam".to_string()
+            };
+            plan.push(SyncAction::RemoveEntry { app_id: entry.app_id.clone(), name: entry.name.clone(), path: entry.path.clone() }, reason);
+        }
+
+        // Icons no launcher installs anymore are collected, whether their launcher was just removed or went away earlier
+        let wanted_icons: HashSet<PathBuf> = sources.iter()
+            .flat_map(|s| s.icons.iter().map(|i| destination.icons_dir.join(&i.path)))
+            .collect();
+        // Launchers that are left in place for apps that are gone keep their icons
+        let removed: HashSet<&Path> = plan.actions.iter()
+            .filter_map(|a| match &a.action { SyncAction::RemoveEntry { path, .. } => Some(path.as_path()), _ => None })
+            .collect();
+        let kept_ids: HashSet<&str> = destination.entries.iter()
+            .filter(|e| !source_ids.contains(e.app_id.as_str()) && !removed.contains(e.path.as_path()))
+            .map(|e| e.app_id.as_str())
+            .collect();
+
+        let mut app_ids: Vec<&String> = destination.icons.keys().filter(|id| !kept_ids.contains(id.as_str())).collect();
+        app_ids.sort();
+        for app_id in app_ids {
+            for icon in &destination.icons[app_id] {
+                let is_steam_icon = icon.file_name().and_then(|n| n.to_str()).map(|n| n.starts_with("steam_icon_")).unwrap_or(false);
+                let is_owned = destination.files.get(icon).map(|f| f.ownership == Ownership::Owned).unwrap_or(false);
+                if !is_steam_icon || !is_owned || wanted_icons.contains(icon) {
+                    continue;
+                }
+
+                let reason = if source_ids.contains(app_id.as_str()) { "no longer used by its launcher" } else { "launcher removed" };
+                plan.push(SyncAction::RemoveIcon { app_id: app_id.clone(), path: icon.clone() }, reason.to_string());
+            }
+        }
+
+        plan.plan_refresh(destination);
+
+        plan
+    }
+
+    /// Works out which icons to install for launchers that exist already, leaving the launchers themselves alone.
+    pub fn compute_icons(sources: &[SourceEntry], destination: &Destination) -> SyncPlan {
+        let mut plan = SyncPlan::default();
+
+        for source in sources {
+            plan.plan_icons(source, destination);
+        }
+
+        plan.plan_refresh(destination);
+        plan
+    }
+
+    fn plan_icons(&mut self, source: &SourceEntry, destination: &Destination) {
+        for icon in &source.icons {
+            let dest = destination.icons_dir.join(&icon.path);
+            match destination.files.get(&dest) {
+                Some(file) if file.hash == icon.hash => {},
+                Some(file) if file.ownership != Ownership::Owned => self.skip(&source.name, &dest, "icon wasn't written by Steam Shortcut Sync"),
+                Some(_) => self.push(SyncAction::CopyIcon { app_id: source.app_id.clone(), src: icon.src.clone(), dest, contents: icon.contents.clone(), source_hash: icon.source_hash.clone() }, "icon changed in Steam".to_string()),
+                None => self.push(SyncAction::CopyIcon { app_id: source.app_id.clone(), src: icon.src.clone(), dest, contents: icon.contents.clone(), source_hash: icon.source_hash.clone() }, "icon missing".to_string())
+            }
+        }
+    }
+
+    fn plan_refresh(&mut self, destination: &Destination) {
+        let changes_entries = self.actions.iter().any(|a| matches!(a.action, SyncAction::CreateEntry { .. } | SyncAction::UpdateEntry { .. } | SyncAction::RemoveEntry { .. }));
+        if changes_entries {
+            self.push(SyncAction::RefreshDatabase { dir: destination.applications_dir.clone() }, "launchers changed".to_string());
+        }
+
+        let changes_icons = self.actions.iter().any(|a| matches!(a.action, SyncAction::CopyIcon { .. } | SyncAction::RemoveIcon { .. }));
+        if changes_icons {
+            self.push(SyncAction::RefreshIconCache { dir: destination.icons_dir.clone() }, "icons changed".to_string());
+        }
+    }
+
+    /// Returns `true` if applying the plan changes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+    }
+
+    /// Carries out every action, recording the files written in `state`.
+    ///
+    /// A failed action is reported and the rest are still applied.
+    pub fn apply(&self, state: &mut SyncState) {
+        for excluded in &self.excluded {
+            println!("Excluding {} ({}): {}", excluded.name, excluded.app_id, excluded.reason);
+        }
+
+        for skipped in &self.skipped {
+            println!("Leaving {} for {} in place: {}", skipped.path.display(), skipped.name, skipped.reason);
+        }
+
+        for planned in &self.actions {
+            match &planned.action {
+                SyncAction::CreateEntry { app_id, name, path, contents } | SyncAction::UpdateEntry { app_id, name, path, contents } => {
+                    match &planned.action {
+                        SyncAction::CreateEntry { .. } => println!("Adding desktop file {}", name),
+                        _ => println!("Updating desktop file {}: {}", name, planned.reason)
+                    }
+
+                    match fs::write(path, contents) {
+                        Ok(_) => state.record(path, ArtifactKind::DesktopFile, app_id, contents.as_bytes()),
+                        Err(e) => eprintln!("Unable to write desktop file {}: {}", name, e)
+                    }
+                },
+                SyncAction::RemoveEntry { name, path, .. } => {
+                    println!("Removing desktop file {}: {}", name, planned.reason);
+                    match fs::remove_file(path) {
+                        Ok(_) => state.forget(path),
+                        Err(e) => eprintln!("Unable to remove desktop file {}: {}", name, e)
+                    }
+                },
+                SyncAction::CopyIcon { app_id, dest, contents, source_hash, .. } => match write_icon(dest, contents) {
+                    Ok(_) => state.record_converted(dest, ArtifactKind::Icon, app_id, contents, source_hash.as_deref()),
+                    Err(e) => eprintln!("Unable to write icon {}: {}", dest.display(), e)
+                },
+                SyncAction::RemoveIcon { path, .. } => {
+                    println!("Removing icon {}: {}", path.display(), planned.reason);
+                    match fs::remove_file(path) {
+                        Ok(_) => state.forget(path),
+                        Err(e) => eprintln!("Unable to remove icon {}: {}", path.display(), e)
+                    }
+                },
+                SyncAction::RefreshDatabase { dir } => match Command::new("update-desktop-database").arg(dir).status() {
+                    Ok(_) => {},
+                    Err(e) => eprintln!("Failed to update desktop database: {}", e)
+                },
+                // The theme has no index.theme of its own, since it extends the system's hicolor theme
+                SyncAction::RefreshIconCache { dir } => match Command::new("gtk-update-icon-cache").args(["--force", "--ignore-theme-index", "--quiet"]).arg(dir).status() {
+                    Ok(_) => {},
+                    Err(e) => eprintln!("Failed to update icon cache: {}", e)
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, action: SyncAction, reason: String) {
+        self.actions.push(PlannedAction { action, reason });
+    }
+
+    fn skip(&mut self, name: &str, path: &Path, reason: &str) {
+        self.skipped.push(SkippedEntry { name: name.to_string(), path: path.to_path_buf(), reason: reason.to_string() });
+    }
+}
+
+impl fmt::Display for SyncPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.actions.is_empty() {
+            writeln!(f, "Nothing to do")?;
+        }
+
+        for planned in &self.actions {
+            match &planned.action {
+                SyncAction::CreateEntry { name, path, .. } => write!(f, "Create {} ({})", path.display(), name)?,
+                SyncAction::UpdateEntry { name, path, .. } => write!(f, "Update {} ({})", path.display(), name)?,
+                SyncAction::RemoveEntry { name, path, .. } => write!(f, "Remove {} ({})", path.display(), name)?,
+                SyncAction::CopyIcon { src, dest, .. } => write!(f, "Copy icon {} to {}", src.display(), dest.display())?,
+                SyncAction::RemoveIcon { path, .. } => write!(f, "Remove icon {}", path.display())?,
+                SyncAction::RefreshDatabase { dir } => write!(f, "Refresh desktop database in {}", dir.display())?,
+                SyncAction::RefreshIconCache { dir } => write!(f, "Refresh icon cache of {}", dir.display())?,
+            }
+            writeln!(f, ": {}", planned.reason)?;
+        }
+
+        for skipped in &self.skipped {
+            writeln!(f, "Leave {} ({}): {}", skipped.path.display(), skipped.name, skipped.reason)?;
+        }
+
+        for excluded in &self.excluded {
+            writeln!(f, "Exclude {} ({}): {}", excluded.name, excluded.app_id, excluded.reason)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Snapshots a file for `Destination::files`, if it exists.
+pub fn existing_file(path: &Path, state: &SyncState) -> Option<ExistingFile> {
+    let contents = fs::read(path).ok()?;
+    Some(ExistingFile { hash: state::hash_contents(&contents), ownership: state.ownership(path) })
+}
+
+fn write_icon(dest: &Path, contents: &[u8]) -> io::Result<()> {
+    if let Some(parent) = dest.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    fs::write(dest, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(app_id: &str, name: &str, contents: &str) -> SourceEntry {
+        SourceEntry {
+            app_id: app_id.to_string(),
+            name: name.to_string(),
+            file_name: format!("steam-app-{}.desktop", app_id),
+            contents: contents.to_string(),
+            icons: Vec::new(),
+        }
+    }
+
+    fn existing(app_id: &str, name: &str, contents: &str, ownership: Ownership) -> ExistingEntry {
+        ExistingEntry {
+            app_id: app_id.to_string(),
+            name: name.to_string(),
+            path: PathBuf::from(format!("/apps/{}.desktop", name)),
+            contents: contents.to_string(),
+            ownership,
+        }
+    }
+
+    fn icon(app_id: &str, size: u32, hash: &str) -> SourceIcon {
+        SourceIcon {
+            src: PathBuf::from(format!("/steam/icons/{}.ico", app_id)),
+            path: PathBuf::from(format!("{0}x{0}/apps/steam_icon_{1}.png", size, app_id)),
+            contents: hash.as_bytes().to_vec(),
+            hash: hash.to_string(),
+            source_hash: None,
+        }
+    }
+
+    fn destination(entries: Vec<ExistingEntry>) -> Destination {
+        Destination { applications_dir: PathBuf::from("/apps"), icons_dir: PathBuf::from("/icons"), entries, ..Default::default() }
+    }
+
+    fn file(hash: &str, ownership: Ownership) -> ExistingFile {
+        ExistingFile { hash: hash.to_string(), ownership }
+    }
+
+    fn summary(plan: &SyncPlan) -> Vec<String> {
+        plan.actions.iter().map(|a| match &a.action {
+            SyncAction::CreateEntry { path, .. } => format!("create {}", path.display()),
+            SyncAction::UpdateEntry { path, .. } => format!("update {}", path.display()),
+            SyncAction::RemoveEntry { path, .. } => format!("remove {}", path.display()),
+            SyncAction::CopyIcon { dest, .. } => format!("copy {}", dest.display()),
+            SyncAction::RemoveIcon { path, .. } => format!("remove icon {}", path.display()),
+            SyncAction::RefreshDatabase { .. } => "refresh database".to_string(),
+            SyncAction::RefreshIconCache { .. } => "refresh icons".to_string(),
+        } + ": " + &a.reason).collect()
+    }
+
+    fn skipped(plan: &SyncPlan) -> Vec<String> {
+        plan.skipped.iter().map(|s| format!("{}: {}", s.path.display(), s.reason)).collect()
+    }
+
+    #[test]
+    fn creates_updates_and_removes_entries() {
+        let sources = [source("10", "New", "a"), source("20", "Renamed", "b"), source("30", "Same", "c"), source("40", "Changed", "new")];
+        let destination = destination(vec![
+            existing("20", "Old Name", "b-old", Ownership::Owned),
+            existing("30", "Same", "c", Ownership::Owned),
+            existing("40", "Changed", "old", Ownership::Owned),
+            existing("50", "Gone", "e", Ownership::Owned),
+        ]);
+
+        let plan = SyncPlan::compute(&sources, &[], &destination, &Config::default());
+
+        assert_eq!(summary(&plan), [
+            "create /apps/steam-app-10.desktop: new in Steam",
+            "update /apps/Old Name.desktop: renamed from Old Name",
+            "update /apps/Changed.desktop: changed in Steam",
+            "remove /apps/Gone.desktop: no longer in Steam",
+            "refresh database: launchers changed",
+        ]);
+        assert!(plan.skipped.is_empty());
+    }
+
+    #[test]
+    fn leaves_files_it_doesnt_own() {
+        let sources = [source("10", "Modified", "new"), source("20", "Foreign", "new"), source("30", "Blocked", "new")];
+        let mut destination = destination(vec![
+            existing("10", "Modified", "old", Ownership::Modified),
+            existing("20", "Foreign", "old", Ownership::Foreign),
+            existing("40", "Gone Modified", "old", Ownership::Modified),
+            existing("50", "Gone Foreign", "old", Ownership::Foreign),
+        ]);
+        destination.files.insert(PathBuf::from("/apps/steam-app-30.desktop"), file("x", Ownership::Foreign));
+
+        let plan = SyncPlan::compute(&sources, &[], &destination, &Config::default());
+
+        assert!(plan.is_empty());
+        assert_eq!(skipped(&plan), [
+            "/apps/Modified.desktop: modified after it was written",
+            "/apps/Foreign.desktop: another launcher already starts this game",
+            "/apps/steam-app-30.desktop: a file that wasn't written by Steam Shortcut Sync is in the way",
+            "/apps/Gone Modified.desktop: modified after it was written",
+            "/apps/Gone Foreign.desktop: not written by Steam Shortcut Sync",
+        ]);
+    }
+
+    #[test]
+    fn prefers_owned_launchers() {
+        let sources = [source("10", "Game", "new")];
+        let destination = destination(vec![
+            existing("10", "Other", "x", Ownership::Foreign),
+            existing("10", "Game", "old", Ownership::Owned),
+        ]);
+
+        let plan = SyncPlan::compute(&sources, &[], &destination, &Config::default());
+
+        assert_eq!(summary(&plan), ["update /apps/Game.desktop: changed in Steam", "refresh database: launchers changed"]);
+        assert!(plan.skipped.is_empty());
+    }
+
+    #[test]
+    fn respects_remove_launchers_and_exclusions() {
+        let destination = destination(vec![existing("10", "Excluded", "a", Ownership::Owned)]);
+        let excluded = [ExcludedEntry { app_id: "10".to_string(), name: "Excluded".to_string(), reason: "filter 1".to_string() }];
+
+        let plan = SyncPlan::compute(&[], &excluded, &destination, &Config::default());
+        assert_eq!(summary(&plan), ["remove /apps/Excluded.desktop: excluded by filter 1", "refresh database: launchers changed"]);
+        assert_eq!(plan.excluded, excluded);
+
+        let config = Config { remove_launchers: false, ..Default::default() };
+        let plan = SyncPlan::compute(&[], &excluded, &destination, &config);
+        assert!(plan.is_empty());
+        assert_eq!(skipped(&plan), ["/apps/Excluded.desktop: removing launchers is disabled"]);
+    }
+
+    #[test]
+    fn plans_icons() {
+        let mut game = source("10", "Game", "a");
+        game.icons = vec![icon("10", 32, "same"), icon("10", 64, "new"), icon("10", 128, "new"), icon("10", 256, "new")];
+        let mut destination = destination(vec![
+            existing("10", "Game", "a", Ownership::Owned),
+            existing("30", "Kept", "c", Ownership::Modified),
+        ]);
+        destination.files.insert(PathBuf::from("/icons/32x32/apps/steam_icon_10.png"), file("same", Ownership::Owned));
+        destination.files.insert(PathBuf::from("/icons/64x64/apps/steam_icon_10.png"), file("old", Ownership::Owned));
+        destination.files.insert(PathBuf::from("/icons/128x128/apps/steam_icon_10.png"), file("old", Ownership::Foreign));
+        destination.files.insert(PathBuf::from("/icons/48x48/apps/steam_icon_10.png"), file("old", Ownership::Owned));
+        destination.files.insert(PathBuf::from("/icons/32x32/apps/steam_icon_20.png"), file("old", Ownership::Owned));
+        destination.files.insert(PathBuf::from("/icons/32x32/apps/steam_icon_30.png"), file("old", Ownership::Owned));
+        destination.icons.insert("10".to_string(), vec![PathBuf::from("/icons/48x48/apps/steam_icon_10.png")]);
+        destination.icons.insert("20".to_string(), vec![PathBuf::from("/icons/32x32/apps/steam_icon_20.png")]);
+        destination.icons.insert("30".to_string(), vec![PathBuf::from("/icons/32x32/apps/steam_icon_30.png")]);
+
+        let plan = SyncPlan::compute(&[game.clone()], &[], &destination, &Config::default());
+
+        assert_eq!(summary(&plan), [
+            "copy /icons/64x64/apps/steam_icon_10.png: icon changed in Steam",
+            "copy /icons/256x256/apps/steam_icon_10.png: icon missing",
+            "remove icon /icons/48x48/apps/steam_icon_10.png: no longer used by its launcher",
+            "remove icon /icons/32x32/apps/steam_icon_20.png: launcher removed",
+            "refresh icons: icons changed",
+        ]);
+        assert_eq!(skipped(&plan)[0], "/icons/128x128/apps/steam_icon_10.png: icon wasn't written by Steam Shortcut Sync");
+
+        // Only the icons are planned for launchers that exist already
+        let plan = SyncPlan::compute_icons(&[game], &destination);
+        assert_eq!(summary(&plan), [
+            "copy /icons/64x64/apps/steam_icon_10.png: icon changed in Steam",
+            "copy /icons/256x256/apps/steam_icon_10.png: icon missing",
+            "refresh icons: icons changed",
+        ]);
+    }
+}
@@ -0,0 +1,426 @@
+//! Reads and writes the non-Steam game shortcuts stored in each Steam user's `userdata/<id>/config/shortcuts.vdf`.
+
+use std::{fs, io, path::{Path, PathBuf}};
+
+use crate::{binary_vdf::{self, BinaryObject, BinaryValue, BinaryVdfParseError}, state, steam};
+
+/// A game added to Steam with "Add a Non-Steam Game".
+#[derive(Debug, Clone, PartialEq)]
+pub struct NonSteamShortcut {
+    /// The 32-bit shortcut app id, with the high bit set.
+    pub appid: u32,
+    /// The name shown in the library.
+    pub app_name: String,
+    /// The executable, usually wrapped in double quotes.
+    pub exe: String,
+    /// The working directory, usually wrapped in double quotes.
+    pub start_dir: String,
+    /// The custom icon path, which is empty if none was set.
+    pub icon: String,
+    /// Arguments appended to the executable.
+    pub launch_options: String,
+    /// Whether the shortcut is hidden from the library.
+    pub is_hidden: bool,
+    /// The user's collections the shortcut belongs to.
+    pub tags: Vec<String>,
+}
+
+impl NonSteamShortcut {
+    /// Creates a shortcut with the app id Steam would give it.
+    pub fn new(app_name: String, exe: String, start_dir: String, icon: String, launch_options: String) -> NonSteamShortcut {
+        NonSteamShortcut {
+            appid: shortcut_appid(&exe, &app_name),
+            app_name,
+            exe,
+            start_dir,
+            icon,
+            launch_options,
+            is_hidden: false,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Builds a shortcut from one entry of the `shortcuts` block.
+    ///
+    /// Clients from before 2020 didn't store an `appid`, in which case it is derived from `Exe` and `AppName` like Steam does.
+    /// Strings that aren't valid UTF-8 are decoded lossily, so only the entry itself keeps their exact bytes.
+    pub fn from_object(object: &BinaryObject) -> NonSteamShortcut {
+        let string = |key| object.get_string_lossy(key).unwrap_or_default().into_owned();
+        let bytes = |key| object.get(key).and_then(BinaryValue::as_bytes).unwrap_or_default();
+
+        let appid = match object.get_i64("appid") {
+            Some(id) if id != 0 => id as u32,
+            _ => shortcut_appid(bytes("Exe"), bytes("AppName"))
+        };
+
+        let tags = object.get_object("tags")
+            .map(|tags| tags.iter().filter_map(|(_, v)| v.to_string_lossy()).map(String::from).collect())
+            .unwrap_or_default();
+
+        NonSteamShortcut {
+            appid,
+            app_name: string("AppName"),
+            exe: string("Exe"),
+            start_dir: string("StartDir"),
+            icon: string("icon"),
+            launch_options: string("LaunchOptions"),
+            is_hidden: object.get_i64("IsHidden").unwrap_or(0) != 0,
+            tags,
+        }
+    }
+
+    /// Writes the shortcut's fields into an entry of the `shortcuts` block.
+    ///
+    /// Keys the shortcut doesn't model, and the entry's tags, are left untouched.
+    pub fn write_into(&self, object: &mut BinaryObject) {
+        object.set("appid", BinaryValue::Int32(self.appid as i32));
+        object.set("AppName", BinaryValue::String(self.app_name.clone().into()));
+        object.set("Exe", BinaryValue::String(self.exe.clone().into()));
+        object.set("StartDir", BinaryValue::String(self.start_dir.clone().into()));
+        object.set("icon", BinaryValue::String(self.icon.clone().into()));
+        object.set("LaunchOptions", BinaryValue::String(self.launch_options.clone().into()));
+    }
+
+    /// Builds a complete `shortcuts` entry with the defaults Steam uses for a new shortcut.
+    fn to_object(&self) -> BinaryObject {
+        let mut object = BinaryObject::default();
+        self.write_into(&mut object);
+
+        object.set("ShortcutPath", BinaryValue::String(Vec::new()));
+        object.set("IsHidden", BinaryValue::Int32(self.is_hidden as i32));
+        object.set("AllowDesktopConfig", BinaryValue::Int32(1));
+        object.set("AllowOverlay", BinaryValue::Int32(1));
+        object.set("OpenVR", BinaryValue::Int32(0));
+        object.set("Devkit", BinaryValue::Int32(0));
+        object.set("DevkitGameID", BinaryValue::String(Vec::new()));
+        object.set("DevkitOverrideAppID", BinaryValue::Int32(0));
+        object.set("LastPlayTime", BinaryValue::Int32(0));
+        object.set("FlatpakAppID", BinaryValue::String(Vec::new()));
+
+        let mut tags = BinaryObject::default();
+        for (i, tag) in self.tags.iter().enumerate() {
+            tags.push(i.to_string(), BinaryValue::String(tag.clone().into()));
+        }
+        object.set("tags", BinaryValue::Object(tags));
+
+        object
+    }
+
+    /// The SHA-256 of the fields `write_into` writes, which changes if the shortcut is edited in Steam.
+    pub fn hash(&self) -> String {
+        let fields = [self.app_name.as_str(), &self.exe, &self.start_dir, &self.icon, &self.launch_options];
+
+        state::hash_contents(fields.join("\0").as_bytes())
+    }
+
+    /// The 64-bit game id used by `steam://rungameid/<id>`.
+    pub fn game_id(&self) -> u64 {
+        game_id(self.appid)
+    }
+
+    /// The custom icon, if one was set.
+    pub fn icon_path(&self) -> Option<PathBuf> {
+        let icon = self.icon.trim_matches('"');
+
+        if icon.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(icon))
+        }
+    }
+}
+
+/// Computes a shortcut app id the way Steam does: the CRC-32 of the executable followed by the name, with the high bit set.
+pub fn shortcut_appid(exe: impl AsRef<[u8]>, app_name: impl AsRef<[u8]>) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(exe.as_ref());
+    hasher.update(app_name.as_ref());
+
+    hasher.finalize() | 0x80000000
+}
+
+/// Converts a 32-bit shortcut app id into the 64-bit game id used by `steam://rungameid/<id>`.
+pub fn game_id(appid: u32) -> u64 {
+    ((appid as u64) << 32) | 0x02000000
+}
+
+/// Possible errors when writing `shortcuts.vdf`.
+#[derive(Debug)]
+pub struct ShortcutWriteError {
+    /// The specific kind of error.
+    pub kind: ShortcutWriteErrorKind
+}
+
+#[derive(Debug)]
+pub enum ShortcutWriteErrorKind {
+    /// Steam is running and would overwrite the file when it exits.
+    SteamRunning,
+    /// The file could not be read, backed up or written.
+    Io(io::Error),
+    /// The existing file was not valid binary KeyValues, so it was left alone.
+    Parse(BinaryVdfParseError),
+}
+
+impl From<io::Error> for ShortcutWriteError {
+    fn from(e: io::Error) -> Self {
+        ShortcutWriteError { kind: ShortcutWriteErrorKind::Io(e) }
+    }
+}
+
+/// Returns the `config` directory of every user of the Steam installation at `install_dir`.
+pub fn user_config_dirs(install_dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(install_dir.join("userdata")) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new()
+    };
+
+    // User directories are named by account id, and `0` is used before anyone logs in
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_str().map(|n| n != "0" && n.parse::<u32>().is_ok()).unwrap_or(false))
+        .map(|e| e.path().join("config"))
+        .filter(|p| p.is_dir())
+        .collect();
+    dirs.sort();
+
+    dirs
+}
+
+/// Returns the path of every `shortcuts.vdf` belonging to a user of the Steam installation at `install_dir`.
+pub fn shortcut_files(install_dir: &Path) -> Vec<PathBuf> {
+    user_config_dirs(install_dir)
+        .into_iter()
+        .map(|p| p.join("shortcuts.vdf"))
+        .filter(|p| p.is_file())
+        .collect()
+}
+
+/// Parses the contents of a `shortcuts.vdf` file.
+pub fn parse_shortcuts(data: &[u8]) -> Result<Vec<NonSteamShortcut>, binary_vdf::BinaryVdfParseError> {
+    let doc = binary_vdf::parse(data)?;
+
+    Ok(doc.get_object("shortcuts")
+        .map(|shortcuts| shortcuts.iter()
+            .filter_map(|(_, v)| v.as_object())
+            .map(NonSteamShortcut::from_object)
+            .collect())
+        .unwrap_or_default())
+}
+
+/// Loads the non-Steam shortcuts of every user of the Steam installation at `install_dir`.
+///
+/// Files that can't be read are skipped.
+pub fn load_shortcuts(install_dir: &Path) -> Vec<NonSteamShortcut> {
+    shortcut_files(install_dir)
+        .into_iter()
+        .flat_map(|path| {
+            let data = match fs::read(&path) {
+                Ok(d) => d,
+                Err(e) => {
+                    eprintln!("Unable to read shortcuts {}: {}", path.display(), e);
+                    return Vec::new();
+                }
+            };
+
+            match parse_shortcuts(&data) {
+                Ok(shortcuts) => shortcuts,
+                Err(e) => {
+                    eprintln!("Unable to parse shortcuts {}: {}", path.display(), e);
+                    Vec::new()
+                }
+            }
+        })
+        .collect()
+}
+
+/// Adds or updates `shortcuts` in a parsed `shortcuts.vdf` document, matching existing entries by app id.
+///
+/// Returns `true` if the document changed.
+pub fn merge_shortcuts(doc: &mut BinaryObject, shortcuts: &[NonSteamShortcut]) -> bool {
+    if doc.get_object("shortcuts").is_none() {
+        doc.set("shortcuts", BinaryValue::Object(BinaryObject::default()));
+    }
+    let block = match doc.get_mut("shortcuts") {
+        Some(BinaryValue::Object(o)) => o,
+        _ => unreachable!()
+    };
+
+    let mut changed = false;
+    for shortcut in shortcuts {
+        let existing = block.iter_mut()
+            .filter_map(|(_, v)| match v {
+                BinaryValue::Object(o) => Some(o),
+                _ => None
+            })
+            .find(|o| NonSteamShortcut::from_object(o).appid == shortcut.appid);
+
+        match existing {
+            Some(object) => {
+                let before = object.clone();
+                shortcut.write_into(object);
+                changed |= *object != before;
+            },
+            None => {
+                // Entries are keyed by index, which Steam renumbers on its next save
+                let index = block.iter()
+                    .filter_map(|(k, _)| k.parse::<usize>().ok())
+                    .max()
+                    .map(|i| i + 1)
+                    .unwrap_or(0);
+                block.push(index.to_string(), BinaryValue::Object(shortcut.to_object()));
+                changed = true;
+            }
+        }
+    }
+
+    changed
+}
+
+/// Removes the entries of a parsed `shortcuts.vdf` document that are in `stale` and still have the hash recorded there.
+///
+/// Entries that were changed since are kept. Returns `true` if the document changed.
+pub fn remove_shortcuts(doc: &mut BinaryObject, stale: &[(u32, String)]) -> bool {
+    let block = match doc.get_object("shortcuts") {
+        Some(block) => block,
+        None => return false
+    };
+
+    let is_stale = |shortcut: NonSteamShortcut| stale.iter().any(|(id, hash)| *id == shortcut.appid && *hash == shortcut.hash());
+    let keys: Vec<String> = block.iter()
+        .filter(|(_, v)| v.as_object().map(|o| is_stale(NonSteamShortcut::from_object(o))).unwrap_or(false))
+        .map(|(k, _)| k.to_string())
+        .collect();
+    if keys.is_empty() {
+        return false;
+    }
+
+    if let Some(BinaryValue::Object(block)) = doc.get_mut("shortcuts") {
+        for key in &keys {
+            block.remove(key);
+        }
+    }
+
+    true
+}
+
+/// Adds or updates `shortcuts` in the `shortcuts.vdf` at `path`, creating it if it doesn't exist, and removes the `stale` ones as `remove_shortcuts` does.
+///
+/// Nothing is written while Steam is running, since it keeps its own copy of the file in memory and overwrites it on exit.
+/// The previous file is kept as `shortcuts.vdf.bak` and the new one is moved into place atomically.
+/// Returns `true` if the file changed.
+pub fn save_shortcuts(path: &Path, shortcuts: &[NonSteamShortcut], stale: &[(u32, String)]) -> Result<bool, ShortcutWriteError> {
+    if steam::is_steam_running() {
+        return Err(ShortcutWriteError { kind: ShortcutWriteErrorKind::SteamRunning });
+    }
+
+    let mut doc = if path.is_file() {
+        let data = fs::read(path)?;
+        binary_vdf::parse(&data).map_err(|e| ShortcutWriteError { kind: ShortcutWriteErrorKind::Parse(e) })?
+    } else {
+        BinaryObject::default()
+    };
+
+    let removed = remove_shortcuts(&mut doc, stale);
+    if !merge_shortcuts(&mut doc, shortcuts) && !removed {
+        return Ok(false);
+    }
+
+    if path.is_file() {
+        fs::copy(path, path.with_extension("vdf.bak"))?;
+    }
+
+    let tmp = path.with_extension("vdf.tmp");
+    fs::write(&tmp, binary_vdf::write(&doc))?;
+    fs::rename(&tmp, path)?;
+
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn firefox() -> NonSteamShortcut {
+        NonSteamShortcut::new("Firefox".to_string(), "\"/usr/bin/firefox\"".to_string(), "\"/usr/bin\"".to_string(), String::new(), String::new())
+    }
+
+    #[test]
+    fn computes_app_ids_like_steam() {
+        assert_eq!(shortcut_appid("\"/usr/bin/firefox\"", "Firefox"), 2910723666);
+        assert_eq!(shortcut_appid("\"C:\\Games\\game.exe\"", "My Game"), 3017974625);
+        assert_eq!(shortcut_appid("", ""), 0x80000000);
+    }
+
+    #[test]
+    fn computes_game_ids() {
+        assert_eq!(game_id(2910723666), 12501462953196781568);
+        assert_eq!(firefox().game_id(), 12501462953196781568);
+    }
+
+    #[test]
+    fn derives_missing_app_ids() {
+        let mut object = BinaryObject::default();
+        object.set("AppName", BinaryValue::String("Firefox".into()));
+        object.set("Exe", BinaryValue::String("\"/usr/bin/firefox\"".into()));
+
+        assert_eq!(NonSteamShortcut::from_object(&object).appid, 2910723666);
+    }
+
+    #[test]
+    fn round_trips_through_shortcuts_vdf() {
+        let mut doc = BinaryObject::default();
+        assert!(merge_shortcuts(&mut doc, &[firefox()]));
+        let parsed = parse_shortcuts(&binary_vdf::write(&doc)).unwrap();
+
+        assert_eq!(parsed, [firefox()]);
+    }
+
+    #[test]
+    fn merges_by_app_id() {
+        let mut doc = BinaryObject::default();
+        merge_shortcuts(&mut doc, &[firefox()]);
+        assert!(!merge_shortcuts(&mut doc, &[firefox()]));
+
+        let mut changed = firefox();
+        changed.launch_options = "--private-window".to_string();
+        assert!(merge_shortcuts(&mut doc, &[changed.clone()]));
+
+        let shortcuts = doc.get_object("shortcuts").unwrap();
+        assert_eq!(shortcuts.len(), 1);
+        assert_eq!(NonSteamShortcut::from_object(shortcuts.get_object("0").unwrap()), changed);
+    }
+
+    #[test]
+    fn keeps_other_entries_byte_for_byte() {
+        let mut user = BinaryObject::default();
+        user.set("appid", BinaryValue::Int32(1));
+        user.set("AppName", BinaryValue::String(b"Caf\xe9".to_vec()));
+        user.set("Exe", BinaryValue::String(b"\"/opt/caf\xe9\"".to_vec()));
+        let mut shortcuts = BinaryObject::default();
+        shortcuts.push("0".to_string(), BinaryValue::Object(user.clone()));
+        let mut doc = BinaryObject::default();
+        doc.push("shortcuts".to_string(), BinaryValue::Object(shortcuts));
+
+        let mut parsed = binary_vdf::parse(&binary_vdf::write(&doc)).unwrap();
+        assert!(merge_shortcuts(&mut parsed, &[firefox()]));
+        let written = binary_vdf::parse(&binary_vdf::write(&parsed)).unwrap();
+        assert_eq!(written.get_object("shortcuts").and_then(|s| s.get_object("0")), Some(&user));
+
+        // The name is only decoded for display, and a legacy app id is derived from the exact bytes
+        user.remove("appid");
+        let shortcut = NonSteamShortcut::from_object(&user);
+        assert_eq!(shortcut.app_name, "Caf\u{fffd}");
+        assert_eq!(shortcut.appid, shortcut_appid(b"\"/opt/caf\xe9\"", b"Caf\xe9"));
+    }
+
+    #[test]
+    fn removes_only_unchanged_stale_shortcuts() {
+        let mut doc = BinaryObject::default();
+        let other = NonSteamShortcut::new("Other".to_string(), "\"/usr/bin/other\"".to_string(), String::new(), String::new(), String::new());
+        merge_shortcuts(&mut doc, &[firefox(), other.clone()]);
+
+        assert!(!remove_shortcuts(&mut doc, &[(firefox().appid, "edited".to_string())]));
+        assert!(remove_shortcuts(&mut doc, &[(firefox().appid, firefox().hash())]));
+        assert_eq!(parse_shortcuts(&binary_vdf::write(&doc)).unwrap(), [other]);
+    }
+}
@@ -0,0 +1,362 @@
+//! Keeps track of every file the daemon writes, so that it never removes a file someone else created.
+//!
+//! The record lives at `$XDG_STATE_HOME/steam-shortcut-sync/state` and stores the SHA-256 of each file as it was written, along with the SHA-256 of the image an icon was converted from.
+//! Shortcuts exported into a `shortcuts.vdf` are recorded the same way, by the file and app id of each one.
+
+use std::{collections::BTreeMap, env::{self, VarError}, ffi::OsStr, fs, io, os::unix::ffi::OsStrExt, path::{Path, PathBuf}, str};
+
+use sha2::{Digest, Sha256};
+
+// Version 2 added the source hash of converted files
+const STATE_VERSION: &str = "v2";
+const STATE_VERSION_1: &str = "v1";
+
+// Exported shortcuts share a file, so they get lines of their own instead of an `ArtifactKind`
+const SHORTCUT_KIND: &str = "shortcut";
+
+/// What kind of file an artifact is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactKind {
+    /// A generated `.desktop` file.
+    DesktopFile,
+    /// An icon copied for a generated `.desktop` file.
+    Icon,
+}
+
+impl ArtifactKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            ArtifactKind::DesktopFile => "desktop",
+            ArtifactKind::Icon => "icon",
+        }
+    }
+
+    fn parse(s: &str) -> Option<ArtifactKind> {
+        match s {
+            "desktop" => Some(ArtifactKind::DesktopFile),
+            "icon" => Some(ArtifactKind::Icon),
+            _ => None
+        }
+    }
+}
+
+/// A file written by the daemon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedArtifact {
+    /// What kind of file it is.
+    pub kind: ArtifactKind,
+    /// The app the file was written for.
+    pub app_id: String,
+    /// The SHA-256 of the contents that were written, in lowercase hex.
+    pub hash: String,
+    /// The SHA-256 of the image the file was converted from, if it wasn't copied as is.
+    pub source: Option<String>,
+}
+
+/// Whether a file on disk may be changed or removed by the daemon.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ownership {
+    /// The daemon wrote the file and it hasn't changed since.
+    Owned,
+    /// The daemon wrote the file, but someone changed it afterwards.
+    Modified,
+    /// The daemon never wrote the file.
+    Foreign,
+}
+
+/// The record of every file the daemon owns.
+#[derive(Debug, Clone)]
+pub struct SyncState {
+    path: PathBuf,
+    artifacts: BTreeMap<PathBuf, OwnedArtifact>,
+    shortcuts: BTreeMap<(PathBuf, u32), String>,
+}
+
+/// Possible errors when loading the state file.
+#[derive(Debug)]
+pub struct StateError {
+    /// The specific kind of error.
+    pub kind: StateErrorKind
+}
+
+#[derive(Debug)]
+pub enum StateErrorKind {
+    /// Neither `XDG_STATE_HOME` nor `HOME` is defined.
+    NoStateDir,
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file was written by an incompatible version or is corrupt.
+    Invalid { line: usize },
+}
+
+impl SyncState {
+    /// Loads the state from its default location, or starts an empty one if there is none yet.
+    pub fn load() -> Result<SyncState, StateError> {
+        let path = state_dir()
+            .map_err(|_| StateError { kind: StateErrorKind::NoStateDir })?
+            .join("state");
+
+        Self::load_from(&path)
+    }
+
+    /// Loads the state stored at `path`, or starts an empty one if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<SyncState, StateError> {
+        let mut state = SyncState { path: path.to_path_buf(), artifacts: BTreeMap::new(), shortcuts: BTreeMap::new() };
+
+        let contents = match fs::read(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(state),
+            Err(e) => return Err(StateError { kind: StateErrorKind::Io(e) })
+        };
+
+        let mut lines = contents.split(|&b| b == b'\n')
+            .enumerate()
+            .filter(|(_, l)| !l.is_empty() && !l.starts_with(b"#"));
+        let has_source = match lines.next() {
+            Some((_, line)) if line == STATE_VERSION.as_bytes() => true,
+            Some((_, line)) if line == STATE_VERSION_1.as_bytes() => false,
+            Some((i, _)) => return Err(StateError { kind: StateErrorKind::Invalid { line: i + 1 } }),
+            None => return Ok(state)
+        };
+
+        for (i, line) in lines {
+            let invalid = StateError { kind: StateErrorKind::Invalid { line: i + 1 } };
+            let (kind, id, hash, source, path) = split_line(line, has_source).ok_or(invalid)?;
+
+            if kind == SHORTCUT_KIND {
+                let appid = id.parse().map_err(|_| StateError { kind: StateErrorKind::Invalid { line: i + 1 } })?;
+                state.shortcuts.insert((path, appid), hash.to_string());
+                continue;
+            }
+
+            let kind = ArtifactKind::parse(kind).ok_or(StateError { kind: StateErrorKind::Invalid { line: i + 1 } })?;
+            let source = Some(source).filter(|s| !s.is_empty()).map(String::from);
+            state.artifacts.insert(path, OwnedArtifact { kind, app_id: id.to_string(), hash: hash.to_string(), source });
+        }
+
+        Ok(state)
+    }
+
+    /// Writes the state back to where it was loaded from, replacing the old file atomically.
+    ///
+    /// Paths are stored as their raw bytes, so ones that aren't valid UTF-8 survive. A path with a line break can't be stored, so its file is left to the user.
+    pub fn save(&self) -> io::Result<()> {
+        let mut contents = format!("# Steam Shortcut Sync state, do not edit\n{}\n", STATE_VERSION).into_bytes();
+        let artifacts = self.artifacts.iter().map(|(path, a)| (a.kind.as_str(), a.app_id.clone(), &a.hash, a.source.as_deref(), path));
+        let shortcuts = self.shortcuts.iter().map(|((path, appid), hash)| (SHORTCUT_KIND, appid.to_string(), hash, None, path));
+        for (kind, id, hash, source, path) in artifacts.chain(shortcuts) {
+            let path = path.as_os_str().as_bytes();
+            if path.contains(&b'\n') {
+                continue;
+            }
+
+            contents.extend(format!("{}\t{}\t{}\t{}\t", kind, id, hash, source.unwrap_or_default()).into_bytes());
+            contents.extend(path);
+            contents.push(b'\n');
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    /// Records that the daemon wrote `contents` to `path`.
+    pub fn record(&mut self, path: &Path, kind: ArtifactKind, app_id: &str, contents: &[u8]) {
+        self.record_converted(path, kind, app_id, contents, None);
+    }
+
+    /// Records that the daemon wrote `contents` to `path` after converting them from an image whose SHA-256 is `source`.
+    pub fn record_converted(&mut self, path: &Path, kind: ArtifactKind, app_id: &str, contents: &[u8], source: Option<&str>) {
+        self.artifacts.insert(path.to_path_buf(), OwnedArtifact {
+            kind,
+            app_id: app_id.to_string(),
+            hash: hash_contents(contents),
+            source: source.map(String::from),
+        });
+    }
+
+    /// Records that the file at `path` was converted from an image whose SHA-256 is `source`, if the daemon wrote it with the SHA-256 `hash`.
+    pub fn record_source(&mut self, path: &Path, hash: &str, source: &str) {
+        if let Some(artifact) = self.artifacts.get_mut(path).filter(|a| a.hash == hash) {
+            artifact.source = Some(source.to_string());
+        }
+    }
+
+    /// Stops tracking `path`, after it was removed or handed over to the user.
+    pub fn forget(&mut self, path: &Path) {
+        self.artifacts.remove(path);
+    }
+
+    /// Moves the record for `from` to `to`, after the file was renamed.
+    pub fn rename(&mut self, from: &Path, to: &Path) {
+        if let Some(artifact) = self.artifacts.remove(from) {
+            self.artifacts.insert(to.to_path_buf(), artifact);
+        }
+    }
+
+    /// Stops tracking files that no longer exist.
+    pub fn prune(&mut self) {
+        self.artifacts.retain(|path, _| path.exists());
+        self.shortcuts.retain(|(path, _), _| path.exists());
+    }
+
+    /// Records that the daemon exported the shortcut `appid` into the `shortcuts.vdf` at `path`, where `hash` identifies what was written.
+    pub fn record_shortcut(&mut self, path: &Path, appid: u32, hash: &str) {
+        self.shortcuts.insert((path.to_path_buf(), appid), hash.to_string());
+    }
+
+    /// Stops tracking an exported shortcut, after it was removed or changed in Steam.
+    pub fn forget_shortcut(&mut self, path: &Path, appid: u32) {
+        self.shortcuts.remove(&(path.to_path_buf(), appid));
+    }
+
+    /// Iterates over the app id of every shortcut the daemon exported into any `shortcuts.vdf`.
+    pub fn shortcut_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.shortcuts.keys().map(|(_, appid)| *appid)
+    }
+
+    /// Iterates over the app id and hash of every shortcut the daemon exported into the `shortcuts.vdf` at `path`.
+    pub fn shortcuts_in<'a>(&'a self, path: &'a Path) -> impl Iterator<Item = (u32, &'a str)> {
+        self.shortcuts.iter()
+            .filter(move |((p, _), _)| p == path)
+            .map(|((_, appid), hash)| (*appid, hash.as_str()))
+    }
+
+    /// Returns the record for `path`, if the daemon wrote it.
+    pub fn get(&self, path: &Path) -> Option<&OwnedArtifact> {
+        self.artifacts.get(path)
+    }
+
+    /// Checks whether the file at `path` may be changed or removed.
+    ///
+    /// A file that no longer exists counts as owned if the daemon wrote it.
+    pub fn ownership(&self, path: &Path) -> Ownership {
+        let artifact = match self.artifacts.get(path) {
+            Some(a) => a,
+            None => return Ownership::Foreign
+        };
+
+        match fs::read(path) {
+            Ok(contents) if hash_contents(&contents) != artifact.hash => Ownership::Modified,
+            _ => Ownership::Owned
+        }
+    }
+
+    /// Iterates over every owned file of one app.
+    pub fn artifacts_for<'a>(&'a self, app_id: &'a str) -> impl Iterator<Item = (&'a Path, &'a OwnedArtifact)> {
+        self.artifacts.iter()
+            .filter(move |(_, a)| a.app_id == app_id)
+            .map(|(p, a)| (p.as_path(), a))
+    }
+
+    /// Iterates over every owned file.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &OwnedArtifact)> {
+        self.artifacts.iter().map(|(p, a)| (p.as_path(), a))
+    }
+}
+
+// Paths go last, since they are the only field that may contain tabs
+fn split_line(line: &[u8], has_source: bool) -> Option<(&str, &str, &str, &str, PathBuf)> {
+    let mut fields = line.splitn(if has_source { 5 } else { 4 }, |&b| b == b'\t');
+    let mut text = || str::from_utf8(fields.next()?).ok();
+    let (kind, id, hash) = (text()?, text()?, text()?);
+    let source = if has_source { text()? } else { "" };
+
+    Some((kind, id, hash, source, PathBuf::from(OsStr::from_bytes(fields.next()?))))
+}
+
+/// Returns the SHA-256 of `contents` in lowercase hex.
+pub fn hash_contents(contents: &[u8]) -> String {
+    Sha256::digest(contents).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The directory the daemon keeps its state in, `$XDG_STATE_HOME/steam-shortcut-sync`.
+pub fn state_dir() -> Result<PathBuf, VarError> {
+    let base = match env::var("XDG_STATE_HOME") {
+        Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => Path::new(&env::var("HOME")?).join(".local/state")
+    };
+
+    Ok(base.join("steam-shortcut-sync"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_artifacts_and_shortcuts() {
+        let dir = env::temp_dir().join(format!("steam-shortcut-sync-state-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let vdf = dir.join("shortcuts.vdf");
+        fs::write(&vdf, b"").unwrap();
+
+        let mut state = SyncState::load_from(&dir.join("state")).unwrap();
+        state.record(&dir.join("steam-app-10.desktop"), ArtifactKind::DesktopFile, "10", b"[Desktop Entry]\n");
+        state.record_shortcut(&vdf, 2910723666, "abc");
+        state.save().unwrap();
+
+        let loaded = SyncState::load_from(&dir.join("state")).unwrap();
+        assert_eq!(loaded.get(&dir.join("steam-app-10.desktop")).map(|a| a.kind), Some(ArtifactKind::DesktopFile));
+        assert_eq!(loaded.shortcuts_in(&vdf).collect::<Vec<_>>(), [(2910723666, "abc")]);
+        assert_eq!(loaded.shortcut_ids().collect::<Vec<_>>(), [2910723666]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_paths_that_are_not_utf8() {
+        let dir = env::temp_dir().join(format!("steam-shortcut-sync-state-bytes-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let odd = dir.join(OsStr::from_bytes(b"caf\xe9\tgames/steam-app-10.desktop"));
+        let broken = dir.join("line\nbreak.desktop");
+
+        let mut state = SyncState::load_from(&dir.join("state")).unwrap();
+        state.record(&odd, ArtifactKind::DesktopFile, "10", b"[Desktop Entry]\n");
+        state.record(&broken, ArtifactKind::DesktopFile, "20", b"[Desktop Entry]\n");
+        state.record_shortcut(&odd, 2910723666, "abc");
+        state.save().unwrap();
+
+        let loaded = SyncState::load_from(&dir.join("state")).unwrap();
+        assert_eq!(loaded.iter().map(|(p, _)| p).collect::<Vec<_>>(), [odd.as_path()]);
+        assert_eq!(loaded.shortcuts_in(&odd).collect::<Vec<_>>(), [(2910723666, "abc")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn keeps_source_hashes_and_reads_version_1() {
+        let dir = env::temp_dir().join(format!("steam-shortcut-sync-state-source-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("state");
+
+        let mut state = SyncState::load_from(&path).unwrap();
+        state.record_converted(&dir.join("icon.png"), ArtifactKind::Icon, "10", b"png", Some("def"));
+        state.save().unwrap();
+        assert_eq!(SyncState::load_from(&path).unwrap().get(&dir.join("icon.png")).and_then(|a| a.source.as_deref()), Some("def"));
+
+        fs::write(&path, "v1\nicon\t10\tabc\t/icons/with\ttab.png\n").unwrap();
+        let artifact = OwnedArtifact { kind: ArtifactKind::Icon, app_id: "10".to_string(), hash: "abc".to_string(), source: None };
+        assert_eq!(SyncState::load_from(&path).unwrap().get(Path::new("/icons/with\ttab.png")), Some(&artifact));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_invalid_lines() {
+        let dir = env::temp_dir().join(format!("steam-shortcut-sync-state-invalid-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("state");
+
+        fs::write(&path, "v2\ndesktop\t10\tabc\t\t/a.desktop\nicon\t10\n").unwrap();
+        assert!(matches!(SyncState::load_from(&path).unwrap_err().kind, StateErrorKind::Invalid { line: 3 }));
+        fs::write(&path, "v0\n").unwrap();
+        assert!(matches!(SyncState::load_from(&path).unwrap_err().kind, StateErrorKind::Invalid { line: 1 }));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
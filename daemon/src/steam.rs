@@ -0,0 +1,160 @@
+//! Discovery of Steam installations.
+//!
+//! Steam can be installed natively, through Flatpak or through Snap, and each of those keeps its files in a different place.
+//! This module probes every known location and reports each distinct Steam root it finds.
+
+use std::{env::{self, VarError}, fs, path::{Path, PathBuf}};
+
+/// The way a Steam installation was packaged.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SteamInstallKind {
+    /// Steam installed from the distribution's packages or Valve's installer.
+    Native,
+    /// The `com.valvesoftware.Steam` Flatpak.
+    Flatpak,
+    /// The `steam` Snap.
+    Snap,
+}
+
+impl SteamInstallKind {
+    /// The command that runs this kind of Steam, which takes a `steam://` URL as its argument.
+    pub fn steam_command(&self) -> &'static str {
+        match self {
+            SteamInstallKind::Native => "steam",
+            SteamInstallKind::Flatpak => "flatpak run com.valvesoftware.Steam",
+            SteamInstallKind::Snap => "snap run steam",
+        }
+    }
+}
+
+/// A Steam installation found on disk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SteamRoot {
+    /// How this installation was packaged.
+    pub kind: SteamInstallKind,
+    /// The Steam directory itself, containing `steamapps`, `userdata` and `appcache`.
+    pub install_dir: PathBuf,
+    /// The XDG data directory Steam writes shortcuts into, containing `applications` and `icons`.
+    pub data_dir: PathBuf,
+}
+
+impl SteamRoot {
+    /// The directory Steam writes its `.desktop` files into.
+    pub fn applications_dir(&self) -> PathBuf {
+        self.data_dir.join("applications")
+    }
+
+    /// The icon theme directory Steam writes its shortcut icons into.
+    pub fn icons_dir(&self) -> PathBuf {
+        self.data_dir.join("icons/hicolor")
+    }
+}
+
+/// Finds every Steam installation for the user whose home directory is in the `HOME` environment variable.
+pub fn discover_steam_roots() -> Result<Vec<SteamRoot>, VarError> {
+    let key = env::var("HOME")?;
+
+    Ok(discover_steam_roots_in(Path::new(&key)))
+}
+
+/// Finds every Steam installation below `home`.
+///
+/// Roots are returned in the order native, Flatpak, Snap.
+/// Locations that resolve to the same directory (such as `~/.steam/root` and `~/.local/share/Steam`) are only reported once.
+pub fn discover_steam_roots_in(home: &Path) -> Vec<SteamRoot> {
+    let flatpak = home.join(".var/app/com.valvesoftware.Steam");
+    let snap = home.join("snap/steam/common");
+
+    let candidates = [
+        // `~/.steam/root` and `~/.steam/steam` are symlinks maintained by the native client
+        (SteamInstallKind::Native, home.join(".steam/root"), home.join(".local/share")),
+        (SteamInstallKind::Native, home.join(".steam/steam"), home.join(".local/share")),
+        (SteamInstallKind::Native, home.join(".local/share/Steam"), home.join(".local/share")),
+        (SteamInstallKind::Flatpak, flatpak.join(".local/share/Steam"), flatpak.join("data")),
+        (SteamInstallKind::Flatpak, flatpak.join(".steam/steam"), flatpak.join("data")),
+        (SteamInstallKind::Snap, snap.join(".local/share/Steam"), snap.join(".local/share")),
+        (SteamInstallKind::Snap, snap.join(".steam/steam"), snap.join(".local/share")),
+    ];
+
+    let mut roots: Vec<SteamRoot> = Vec::new();
+
+    for (kind, path, data_dir) in candidates {
+        // Follows symlinks so that every alias of a root collapses into one entry
+        let install_dir = match fs::canonicalize(&path) {
+            Ok(p) if p.is_dir() => p,
+            _ => continue
+        };
+
+        if roots.iter().any(|r| r.install_dir == install_dir) {
+            continue;
+        }
+
+        roots.push(SteamRoot { kind, install_dir, data_dir });
+    }
+
+    roots
+}
+
+/// Returns `true` if a Steam client process is running, whichever way it was installed.
+pub fn is_steam_running() -> bool {
+    let entries = match fs::read_dir("/proc") {
+        Ok(entries) => entries,
+        Err(_) => return false
+    };
+
+    entries
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_name().to_str().map(|n| n.parse::<u32>().is_ok()).unwrap_or(false))
+        .filter_map(|e| fs::read_to_string(e.path().join("comm")).ok())
+        .any(|comm| comm.trim_end() == "steam")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::symlink, process};
+
+    use super::*;
+
+    #[test]
+    fn discovers_every_kind_once_in_order() {
+        let home = env::temp_dir().join(format!("steam-shortcut-sync-steam-{}", process::id()));
+        let _ = fs::remove_dir_all(&home);
+        let native = home.join(".local/share/Steam");
+        let flatpak = home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam");
+        let snap = home.join("snap/steam/common/.steam/steam");
+        for dir in [&native, &flatpak, &snap] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        // The native client's aliases point at the same directory
+        fs::create_dir_all(home.join(".steam")).unwrap();
+        symlink(&native, home.join(".steam/steam")).unwrap();
+        symlink(&native, home.join(".steam/root")).unwrap();
+        let home = fs::canonicalize(&home).unwrap();
+
+        let roots = discover_steam_roots_in(&home);
+
+        assert_eq!(roots, [
+            SteamRoot { kind: SteamInstallKind::Native, install_dir: home.join(".local/share/Steam"), data_dir: home.join(".local/share") },
+            SteamRoot { kind: SteamInstallKind::Flatpak, install_dir: home.join(".var/app/com.valvesoftware.Steam/.local/share/Steam"), data_dir: home.join(".var/app/com.valvesoftware.Steam/data") },
+            SteamRoot { kind: SteamInstallKind::Snap, install_dir: home.join("snap/steam/common/.steam/steam"), data_dir: home.join("snap/steam/common/.local/share") },
+        ]);
+        assert_eq!(roots[1].applications_dir(), home.join(".var/app/com.valvesoftware.Steam/data/applications"));
+        assert_eq!(roots[0].icons_dir(), home.join(".local/share/icons/hicolor"));
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+
+    #[test]
+    fn ignores_dangling_links_and_files() {
+        let home = env::temp_dir().join(format!("steam-shortcut-sync-steam-empty-{}", process::id()));
+        let _ = fs::remove_dir_all(&home);
+        fs::create_dir_all(home.join(".steam")).unwrap();
+        fs::create_dir_all(home.join(".local/share")).unwrap();
+        symlink(home.join("gone"), home.join(".steam/steam")).unwrap();
+        fs::write(home.join(".local/share/Steam"), b"").unwrap();
+
+        assert!(discover_steam_roots_in(&home).is_empty());
+
+        fs::remove_dir_all(&home).unwrap();
+    }
+}
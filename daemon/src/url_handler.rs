@@ -0,0 +1,670 @@
+//! Registration of a handler for `steam://` URLs.
+//!
+//! Launchers that use `xdg-open` open `steam://` URLs, which do nothing unless an application is associated with `x-scheme-handler/steam`.
+//! The association is looked up in the `mimeapps.list` files of every XDG directory, the way desktops do.
+//! If nothing installed handles the scheme, a handler for a discovered Steam is written and made the default.
+//! What was changed is kept at `$XDG_STATE_HOME/steam-shortcut-sync/url-handler`, so it can be undone.
+
+use std::{env::{self, VarError}, fmt, fs, io, path::{Path, PathBuf}};
+
+use crate::{desktop::{self, DesktopEntry}, state::{self, StateError, StateErrorKind}, steam::{SteamInstallKind, SteamRoot}};
+
+/// The MIME type desktops associate `steam://` URLs with.
+pub const SCHEME_MIME_TYPE: &str = "x-scheme-handler/steam";
+
+/// The desktop file id of the handler the daemon installs.
+pub const HANDLER_ID: &str = "steam-shortcut-sync-url-handler.desktop";
+
+const DEFAULT_GROUP: &str = "Default Applications";
+const ADDED_GROUP: &str = "Added Associations";
+const REMOVED_GROUP: &str = "Removed Associations";
+
+/// Whether `steam://` URLs can be opened.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandlerStatus {
+    /// The desktop file `id` handles the scheme and what it runs is installed.
+    Working { id: String, path: PathBuf },
+    /// The desktop file `id` is associated with the scheme, but it or what it runs isn't installed.
+    Broken { id: String, reason: String },
+    /// Nothing is associated with the scheme.
+    Missing,
+}
+
+impl HandlerStatus {
+    /// Returns `true` if `steam://` URLs can be opened.
+    pub fn is_working(&self) -> bool {
+        matches!(self, HandlerStatus::Working { .. })
+    }
+}
+
+impl fmt::Display for HandlerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandlerStatus::Working { id, path } => write!(f, "handled by {} ({})", id, path.display()),
+            HandlerStatus::Broken { id, reason } => write!(f, "associated with {}, but {}", id, reason),
+            HandlerStatus::Missing => write!(f, "no application is associated with {}", SCHEME_MIME_TYPE),
+        }
+    }
+}
+
+/// The XDG base directories associations and desktop files are looked up in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XdgDirs {
+    /// `$XDG_CONFIG_HOME`, which holds the user's `mimeapps.list`.
+    pub config_home: PathBuf,
+    /// `$XDG_CONFIG_DIRS`, most important first.
+    pub config_dirs: Vec<PathBuf>,
+    /// `$XDG_DATA_HOME`, which holds the user's desktop files.
+    pub data_home: PathBuf,
+    /// `$XDG_DATA_DIRS`, most important first.
+    pub data_dirs: Vec<PathBuf>,
+    /// `$XDG_CURRENT_DESKTOP` in lowercase, whose own `mimeapps.list` files come first.
+    pub desktops: Vec<String>,
+}
+
+impl XdgDirs {
+    /// Reads the directories from the environment, with the defaults of the base directory specification.
+    pub fn from_env() -> Result<XdgDirs, VarError> {
+        let home = PathBuf::from(env::var("HOME")?);
+        let var = |key| env::var(key).ok().filter(|v| !v.is_empty());
+        let list = |key, default: &str| -> Vec<PathBuf> {
+            var(key).unwrap_or_else(|| default.to_string()).split(':').filter(|d| !d.is_empty()).map(PathBuf::from).collect()
+        };
+
+        Ok(XdgDirs {
+            config_home: var("XDG_CONFIG_HOME").map(PathBuf::from).unwrap_or_else(|| home.join(".config")),
+            config_dirs: list("XDG_CONFIG_DIRS", "/etc/xdg"),
+            data_home: var("XDG_DATA_HOME").map(PathBuf::from).unwrap_or_else(|| home.join(".local/share")),
+            data_dirs: list("XDG_DATA_DIRS", "/usr/local/share:/usr/share"),
+            desktops: var("XDG_CURRENT_DESKTOP").map(|d| d.split(':').map(str::to_lowercase).collect()).unwrap_or_default(),
+        })
+    }
+
+    /// Every `mimeapps.list` in the order associations are looked up in.
+    ///
+    /// The deprecated `defaults.list` that distributions still ship is looked up after the `mimeapps.list` of the same `applications` directory.
+    pub fn mimeapps_lists(&self) -> Vec<PathBuf> {
+        let mut names: Vec<String> = self.desktops.iter().map(|d| format!("{}-mimeapps.list", d)).collect();
+        names.push("mimeapps.list".to_string());
+
+        let config = std::iter::once(&self.config_home).chain(&self.config_dirs)
+            .flat_map(|dir| names.iter().map(move |name| dir.join(name)));
+        let data = self.applications_dirs().into_iter()
+            .flat_map(|dir| names.iter().map(|name| dir.join(name)).chain([dir.join("defaults.list")]).collect::<Vec<_>>());
+
+        config.chain(data).collect()
+    }
+
+    /// Every `applications` directory, most important first.
+    pub fn applications_dirs(&self) -> Vec<PathBuf> {
+        std::iter::once(&self.data_home).chain(&self.data_dirs).map(|d| d.join("applications")).collect()
+    }
+
+    /// Finds the desktop file with the id `id`, where a `-` may also stand for a subdirectory.
+    pub fn find_desktop_file(&self, id: &str) -> Option<PathBuf> {
+        let nested = id.replacen('-', "/", 1);
+
+        self.applications_dirs().into_iter()
+            .flat_map(|dir| [dir.join(id), dir.join(&nested)])
+            .find(|path| path.is_file())
+    }
+}
+
+/// What registering the handler changed, so it can be undone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HandlerRecord {
+    path: PathBuf,
+    /// The handler desktop file and the SHA-256 of what was written to it.
+    handler: Option<(PathBuf, String)>,
+    /// The `mimeapps.list` whose default was replaced, and the raw value it had before, which is `None` if it had none.
+    replaced: Option<(PathBuf, Option<String>)>,
+}
+
+impl HandlerRecord {
+    /// Loads the record from its default location, or starts an empty one if nothing was registered.
+    pub fn load() -> Result<HandlerRecord, StateError> {
+        let path = state::state_dir()
+            .map_err(|_| StateError { kind: StateErrorKind::NoStateDir })?
+            .join("url-handler");
+
+        Self::load_from(&path)
+    }
+
+    /// Loads the record stored at `path`, or starts an empty one if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<HandlerRecord, StateError> {
+        let mut record = HandlerRecord { path: path.to_path_buf(), ..Default::default() };
+
+        let contents = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(record),
+            Err(e) => return Err(StateError { kind: StateErrorKind::Io(e) })
+        };
+
+        for (i, line) in contents.lines().enumerate().filter(|(_, l)| !l.starts_with('#') && !l.is_empty()) {
+            // Paths go last, since they may contain tabs
+            let fields: Vec<&str> = line.splitn(3, '\t').collect();
+            match fields[..] {
+                ["handler", hash, path] => record.handler = Some((PathBuf::from(path), hash.to_string())),
+                ["default", value, path] => record.replaced = Some((PathBuf::from(path), Some(desktop::unescape(value)))),
+                ["no-default", "", path] => record.replaced = Some((PathBuf::from(path), None)),
+                _ => return Err(StateError { kind: StateErrorKind::Invalid { line: i + 1 } })
+            }
+        }
+
+        Ok(record)
+    }
+
+    /// Writes the record back to where it was loaded from, or removes the file if there is nothing left to undo.
+    pub fn save(&self) -> io::Result<()> {
+        if self.is_empty() {
+            return match fs::remove_file(&self.path) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+                _ => Ok(())
+            };
+        }
+
+        let mut contents = String::from("# steam:// handler registered by Steam Shortcut Sync, undone with --unregister-url-handler\n");
+        if let Some((path, hash)) = &self.handler {
+            contents.push_str(&format!("handler\t{}\t{}\n", hash, path.display()));
+        }
+        match &self.replaced {
+            // Tabs in the value are escaped, so it stays a single field
+            Some((path, Some(value))) => contents.push_str(&format!("default\t{}\t{}\n", desktop::escape(value), path.display())),
+            Some((path, None)) => contents.push_str(&format!("no-default\t\t{}\n", path.display())),
+            None => {}
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    /// Returns `true` if nothing was registered.
+    pub fn is_empty(&self) -> bool {
+        self.handler.is_none() && self.replaced.is_none()
+    }
+}
+
+/// Works out what opens `steam://` URLs, following the association rules of the MIME applications specification.
+pub fn status(dirs: &XdgDirs) -> HandlerStatus {
+    let lists: Vec<String> = dirs.mimeapps_lists().iter().filter_map(|p| fs::read_to_string(p).ok()).collect();
+    let mut removed: Vec<String> = Vec::new();
+    let mut added: Vec<String> = Vec::new();
+    let mut stale = None;
+
+    // The first default that is installed wins, and associations removed in one file stay removed in less important ones
+    for contents in &lists {
+        for id in associations(contents, DEFAULT_GROUP) {
+            match dirs.find_desktop_file(&id) {
+                Some(path) => return check_handler(id, path),
+                None => { stale.get_or_insert(id); }
+            }
+        }
+
+        added.extend(associations(contents, ADDED_GROUP).into_iter().filter(|id| !removed.contains(id)));
+        removed.extend(associations(contents, REMOVED_GROUP));
+    }
+
+    for id in added {
+        if let Some(path) = dirs.find_desktop_file(&id) {
+            return check_handler(id, path);
+        }
+    }
+
+    // Without an explicit association, any desktop file that lists the scheme in its `MimeType` handles it
+    for dir in dirs.applications_dirs() {
+        let mut paths: Vec<PathBuf> = match fs::read_dir(&dir) {
+            Ok(entries) => entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.extension().map(|e| e == "desktop").unwrap_or(false)).collect(),
+            Err(_) => continue
+        };
+        paths.sort();
+
+        for path in paths {
+            let id = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+            let handles = load_desktop_file(&path)
+                .and_then(|e| e.desktop_entry()?.get_strings("MimeType"))
+                .map(|types| types.iter().any(|t| t == SCHEME_MIME_TYPE))
+                .unwrap_or(false);
+            if handles && !removed.contains(&id) {
+                return check_handler(id, path);
+            }
+        }
+    }
+
+    match stale {
+        Some(id) => HandlerStatus::Broken { id, reason: "it is not installed".to_string() },
+        None => HandlerStatus::Missing
+    }
+}
+
+/// Installs a handler that opens `steam://` URLs with `root` and makes it the user's default.
+///
+/// The default it replaces is kept in `record`, unless an earlier registration already replaced it.
+pub fn register(dirs: &XdgDirs, root: &SteamRoot, record: &mut HandlerRecord) -> io::Result<PathBuf> {
+    let mut entry = DesktopEntry::new();
+    let group = entry.desktop_entry_mut();
+    group.set_string("Type", "Application");
+    group.set_string("Name", "Steam");
+    group.set_string("Comment", "Opens steam:// links, installed by Steam Shortcut Sync");
+    group.set_string("Icon", "steam");
+    group.set_string("Exec", &format!("{} %u", root.kind.steam_command()));
+    group.set_strings("MimeType", &[SCHEME_MIME_TYPE]);
+    group.set_bool("NoDisplay", true);
+    let contents = entry.to_string();
+
+    let handler = dirs.data_home.join("applications").join(HANDLER_ID);
+    if let Some(parent) = handler.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(&handler, &contents)?;
+    record.handler = Some((handler.clone(), state::hash_contents(contents.as_bytes())));
+
+    let list = dirs.config_home.join("mimeapps.list");
+    let previous = match fs::read_to_string(&list) {
+        Ok(c) => c,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(e) => return Err(e)
+    };
+    if record.replaced.is_none() {
+        record.replaced = Some((list.clone(), raw_value(&previous, DEFAULT_GROUP)));
+    }
+
+    let updated = set_value(&previous, DEFAULT_GROUP, Some(&format!("{};", HANDLER_ID)));
+    if updated != previous {
+        fs::create_dir_all(&dirs.config_home)?;
+        let tmp = list.with_extension("tmp");
+        fs::write(&tmp, updated)?;
+        fs::rename(&tmp, &list)?;
+    }
+
+    Ok(handler)
+}
+
+/// Undoes `register`, restoring the previous default and removing the handler.
+///
+/// A default that was changed since is left alone, and so is a handler that was edited.
+/// Returns what was undone, one line each.
+pub fn unregister(record: &mut HandlerRecord) -> io::Result<Vec<String>> {
+    let mut undone = Vec::new();
+
+    if let Some((list, value)) = record.replaced.clone() {
+        let contents = match fs::read_to_string(&list) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e)
+        };
+
+        if associations(&contents, DEFAULT_GROUP).first().map(|id| id == HANDLER_ID).unwrap_or(false) {
+            let tmp = list.with_extension("tmp");
+            fs::write(&tmp, set_value(&contents, DEFAULT_GROUP, value.as_deref()))?;
+            fs::rename(&tmp, &list)?;
+            undone.push(format!("Restored the default {} handler in {}", SCHEME_MIME_TYPE, list.display()));
+        }
+        record.replaced = None;
+    }
+
+    if let Some((handler, hash)) = record.handler.clone() {
+        match fs::read(&handler) {
+            Ok(contents) if state::hash_contents(&contents) == hash => {
+                fs::remove_file(&handler)?;
+                undone.push(format!("Removed {}", handler.display()));
+            },
+            Ok(_) => undone.push(format!("Kept {}, since it was changed", handler.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+            Err(e) => return Err(e)
+        }
+        record.handler = None;
+    }
+
+    record.save()?;
+    Ok(undone)
+}
+
+fn load_desktop_file(path: &Path) -> Option<DesktopEntry> {
+    DesktopEntry::parse(&fs::read_to_string(path).ok()?).ok()
+}
+
+// An association only works if the desktop file and the program it runs are both installed
+fn check_handler(id: String, path: PathBuf) -> HandlerStatus {
+    let entry = match load_desktop_file(&path) {
+        Some(entry) if !entry.hidden() => entry,
+        Some(_) => return HandlerStatus::Broken { id, reason: "it is hidden".to_string() },
+        None => return HandlerStatus::Broken { id, reason: format!("{} can't be read", path.display()) }
+    };
+
+    let try_exec = entry.desktop_entry().and_then(|g| g.get_string("TryExec"));
+    if let Some(program) = try_exec.filter(|p| !program_exists(p)) {
+        return HandlerStatus::Broken { id, reason: format!("{} is not installed", program) };
+    }
+
+    match missing_program(&desktop::split_exec(&entry.exec().unwrap_or_default())) {
+        Some(reason) => HandlerStatus::Broken { id, reason },
+        None => HandlerStatus::Working { id, path }
+    }
+}
+
+/// Returns `true` if the command that runs `kind` is installed, so a handler for it would work.
+pub fn can_run(kind: SteamInstallKind) -> bool {
+    missing_program(&desktop::split_exec(kind.steam_command())).is_none()
+}
+
+// Says what isn't installed for a command line to run
+fn missing_program(args: &[String]) -> Option<String> {
+    let program = match args.first() {
+        Some(program) => program,
+        None => return Some("it has no Exec".to_string())
+    };
+    if !program_exists(program) {
+        return Some(format!("{} is not installed", program));
+    }
+
+    // `flatpak` outlives the apps it runs, so the app itself has to be checked
+    let is_flatpak = Path::new(program).file_name().map(|n| n == "flatpak").unwrap_or(false);
+    if is_flatpak && args.get(1).map(|a| a == "run").unwrap_or(false) {
+        if let Some(app) = args[2..].iter().find(|a| !a.starts_with('-')) {
+            if !flatpak_installed(app) {
+                return Some(format!("the Flatpak {} is not installed", app));
+            }
+        }
+    }
+
+    None
+}
+
+fn program_exists(program: &str) -> bool {
+    if program.contains('/') {
+        return Path::new(program).is_file();
+    }
+
+    env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).any(|dir| dir.join(program).is_file()))
+        .unwrap_or(false)
+}
+
+fn flatpak_installed(app: &str) -> bool {
+    let user = env::var("HOME").map(|home| Path::new(&home).join(".local/share/flatpak"));
+
+    user.into_iter()
+        .chain([PathBuf::from("/var/lib/flatpak")])
+        .any(|installation| installation.join("app").join(app).is_dir())
+}
+
+// `mimeapps.list` has Desktop Entry syntax, but its keys contain `/`, which the desktop parser rejects
+fn raw_value(contents: &str, group: &str) -> Option<String> {
+    let mut current = None;
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            current = Some(&trimmed[1..trimmed.len() - 1]);
+        } else if current == Some(group) {
+            if let Some((key, value)) = line.split_once('=') {
+                // The value is kept exactly as written, so that restoring it gives back the same line
+                if key.trim() == SCHEME_MIME_TYPE {
+                    return Some(value.to_string());
+                }
+            }
+        }
+    }
+
+    None
+}
+
+fn associations(contents: &str, group: &str) -> Vec<String> {
+    raw_value(contents, group)
+        .map(|value| value.split(';').map(str::trim).filter(|id| !id.is_empty()).map(String::from).collect())
+        .unwrap_or_default()
+}
+
+// Replaces or removes the scheme's line in `group`, leaving every other byte as it was
+fn set_value(contents: &str, group: &str, value: Option<&str>) -> String {
+    // Lines are split on `\n` alone, so that `\r\n` endings and a missing final line break survive
+    let ends_with_newline = contents.is_empty() || contents.ends_with('\n');
+    let mut lines: Vec<String> = contents.split('\n').map(String::from).collect();
+    if ends_with_newline {
+        lines.pop();
+    }
+    let mut current = None;
+    let mut group_start = None;
+    let mut existing = None;
+
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.starts_with('[') && trimmed.ends_with(']') {
+            current = Some(trimmed[1..trimmed.len() - 1].to_string());
+            if current.as_deref() == Some(group) {
+                group_start = Some(i);
+            }
+        } else if current.as_deref() == Some(group) && trimmed.split_once('=').map(|(k, _)| k.trim() == SCHEME_MIME_TYPE).unwrap_or(false) {
+            existing = Some(i);
+        }
+    }
+
+    let line = value.map(|v| format!("{}={}", SCHEME_MIME_TYPE, v));
+    match (existing, line) {
+        // The key is written as it was, spaces around `=` included
+        (Some(i), Some(_)) => {
+            let old = &lines[i];
+            let key_end = old.find('=').unwrap_or_default() + 1;
+            let ending = if old.ends_with('\r') { "\r" } else { "" };
+            lines[i] = format!("{}{}{}", &old[..key_end], value.unwrap_or_default(), ending);
+        },
+        (Some(i), None) => { lines.remove(i); },
+        (None, Some(line)) => match group_start {
+            Some(i) => lines.insert(i + 1, line),
+            None => {
+                if lines.last().map(|l| !l.trim().is_empty()).unwrap_or(false) {
+                    lines.push(String::new());
+                }
+                lines.push(format!("[{}]", group));
+                lines.push(line);
+            }
+        },
+        (None, None) => {}
+    }
+
+    let mut updated = lines.join("\n");
+    if ends_with_newline && !lines.is_empty() {
+        updated.push('\n');
+    }
+    updated
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    struct TempXdg {
+        root: PathBuf,
+        dirs: XdgDirs,
+    }
+
+    impl TempXdg {
+        fn new(name: &str) -> TempXdg {
+            let root = env::temp_dir().join(format!("steam-shortcut-sync-url-handler-{}-{}", name, process::id()));
+            let _ = fs::remove_dir_all(&root);
+            fs::create_dir_all(root.join("bin")).unwrap();
+            fs::write(root.join("bin/steam"), "").unwrap();
+
+            let dirs = XdgDirs {
+                config_home: root.join("config"),
+                config_dirs: vec![root.join("etc")],
+                data_home: root.join("data"),
+                data_dirs: vec![root.join("usr")],
+                desktops: Vec::new(),
+            };
+            TempXdg { root, dirs }
+        }
+
+        fn write(&self, path: &str, contents: &str) {
+            let path = self.root.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, contents).unwrap();
+        }
+
+        // A handler that runs a program that exists
+        fn handler(&self, path: &str, extra: &str) {
+            self.write(path, &format!("[Desktop Entry]\nType=Application\nName=Steam\nExec={} %u\n{}", self.root.join("bin/steam").display(), extra));
+        }
+
+        fn status_id(&self) -> Option<String> {
+            match status(&self.dirs) {
+                HandlerStatus::Working { id, .. } => Some(id),
+                _ => None
+            }
+        }
+    }
+
+    impl Drop for TempXdg {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn default_list(id: &str) -> String {
+        format!("[Default Applications]\n{}={};\n", SCHEME_MIME_TYPE, id)
+    }
+
+    #[test]
+    fn sets_values_in_their_group() {
+        // The group is created when it is missing
+        assert_eq!(set_value("", DEFAULT_GROUP, Some("a.desktop;")), "[Default Applications]\nx-scheme-handler/steam=a.desktop;\n");
+        assert_eq!(set_value("[Added Associations]\ntext/plain=b.desktop;", DEFAULT_GROUP, Some("a.desktop;")),
+            "[Added Associations]\ntext/plain=b.desktop;\n\n[Default Applications]\nx-scheme-handler/steam=a.desktop;");
+
+        // Other keys, comments and line endings stay as they were
+        let contents = "# Mine\r\n[Default Applications]\r\ntext/plain=b.desktop;\r\nx-scheme-handler/steam = old.desktop; \r\n";
+        let updated = set_value(contents, DEFAULT_GROUP, Some("a.desktop;"));
+        assert_eq!(updated, "# Mine\r\n[Default Applications]\r\ntext/plain=b.desktop;\r\nx-scheme-handler/steam =a.desktop;\r\n");
+        assert_eq!(raw_value(contents, DEFAULT_GROUP).as_deref(), Some(" old.desktop; "));
+        assert_eq!(raw_value(&updated, DEFAULT_GROUP).as_deref(), Some("a.desktop;"));
+        assert_eq!(set_value(&updated, DEFAULT_GROUP, Some(" old.desktop; ")), contents);
+
+        // Restoring no value removes the key
+        let contents = "[Default Applications]\ntext/plain=b.desktop;\n";
+        let added = set_value(contents, DEFAULT_GROUP, Some("a.desktop;"));
+        assert_eq!(added, "[Default Applications]\nx-scheme-handler/steam=a.desktop;\ntext/plain=b.desktop;\n");
+        assert_eq!(set_value(&added, DEFAULT_GROUP, None), contents);
+        assert_eq!(raw_value(contents, DEFAULT_GROUP), None);
+        assert_eq!(raw_value("[Added Associations]\nx-scheme-handler/steam=a.desktop;", DEFAULT_GROUP), None);
+    }
+
+    #[test]
+    fn resolves_the_handler_in_lookup_order() {
+        let xdg = TempXdg::new("status");
+        for id in ["a", "b", "c", "d"] {
+            xdg.handler(&format!("usr/applications/{}.desktop", id), "");
+        }
+        xdg.handler("usr/applications/e.desktop", &format!("MimeType={};\n", SCHEME_MIME_TYPE));
+        assert_eq!(xdg.status_id().as_deref(), Some("e.desktop"));
+
+        // Defaults come first: the user's, then the system's, then the deprecated `defaults.list`
+        xdg.write("usr/applications/defaults.list", &default_list("d.desktop"));
+        assert_eq!(xdg.status_id().as_deref(), Some("d.desktop"));
+        xdg.write("usr/applications/mimeapps.list", &default_list("c.desktop"));
+        assert_eq!(xdg.status_id().as_deref(), Some("c.desktop"));
+        xdg.write("etc/mimeapps.list", &default_list("b.desktop"));
+        assert_eq!(xdg.status_id().as_deref(), Some("b.desktop"));
+        xdg.write("config/mimeapps.list", &default_list("a.desktop"));
+        assert_eq!(xdg.status_id().as_deref(), Some("a.desktop"));
+
+        // A default that isn't installed is skipped
+        xdg.write("config/mimeapps.list", &default_list("gone.desktop"));
+        assert_eq!(xdg.status_id().as_deref(), Some("b.desktop"));
+
+        // Without defaults, added associations come next, unless a more important list removed them
+        for list in ["etc/mimeapps.list", "usr/applications/mimeapps.list", "usr/applications/defaults.list"] {
+            fs::remove_file(xdg.root.join(list)).unwrap();
+        }
+        xdg.write("config/mimeapps.list", &format!("[Added Associations]\n{}=c.desktop;\n", SCHEME_MIME_TYPE));
+        assert_eq!(xdg.status_id().as_deref(), Some("c.desktop"));
+        xdg.write("config/mimeapps.list", &format!("[Removed Associations]\n{0}=c.desktop;e.desktop;\n", SCHEME_MIME_TYPE));
+        xdg.write("etc/mimeapps.list", &format!("[Added Associations]\n{}=c.desktop;\n", SCHEME_MIME_TYPE));
+        assert_eq!(status(&xdg.dirs), HandlerStatus::Missing);
+
+        xdg.write("config/mimeapps.list", &default_list("gone.desktop"));
+        fs::remove_file(xdg.root.join("etc/mimeapps.list")).unwrap();
+        fs::remove_file(xdg.root.join("usr/applications/e.desktop")).unwrap();
+        assert!(matches!(status(&xdg.dirs), HandlerStatus::Broken { id, .. } if id == "gone.desktop"));
+        xdg.handler("usr/applications/gone.desktop", "Hidden=true\n");
+        assert!(matches!(status(&xdg.dirs), HandlerStatus::Broken { reason, .. } if reason == "it is hidden"));
+    }
+
+    #[test]
+    fn unregistering_restores_the_previous_default() {
+        let root = SteamRoot { kind: SteamInstallKind::Native, install_dir: PathBuf::from("/steam"), data_dir: PathBuf::from("/data") };
+        let previous = [
+            Some("# Mine\n[Default Applications]\ntext/plain=b.desktop;\nx-scheme-handler/steam = old.desktop; \n"),
+            // An empty value is restored as empty rather than removed
+            Some("[Default Applications]\nx-scheme-handler/steam=\n"),
+            Some("[Default Applications]\ntext/plain=b.desktop;"),
+            None,
+        ];
+
+        for (i, contents) in previous.into_iter().enumerate() {
+            let xdg = TempXdg::new(&format!("register-{}", i));
+            let list = xdg.dirs.config_home.join("mimeapps.list");
+            if let Some(contents) = contents {
+                xdg.write("config/mimeapps.list", contents);
+            }
+
+            let record_path = xdg.root.join("state/url-handler");
+            let mut record = HandlerRecord::load_from(&record_path).unwrap();
+            let handler = register(&xdg.dirs, &root, &mut record).unwrap();
+            record.save().unwrap();
+            assert_eq!(associations(&fs::read_to_string(&list).unwrap(), DEFAULT_GROUP), [HANDLER_ID]);
+
+            let mut record = HandlerRecord::load_from(&record_path).unwrap();
+            assert_eq!(unregister(&mut record).unwrap().len(), 2);
+            assert!(!handler.exists() && !record_path.exists());
+            match contents {
+                Some(contents) => assert_eq!(fs::read_to_string(&list).unwrap(), contents),
+                None => assert_eq!(raw_value(&fs::read_to_string(&list).unwrap(), DEFAULT_GROUP), None)
+            }
+        }
+    }
+
+    #[test]
+    fn leaves_changed_registrations_alone() {
+        let xdg = TempXdg::new("changed");
+        let root = SteamRoot { kind: SteamInstallKind::Native, install_dir: PathBuf::from("/steam"), data_dir: PathBuf::from("/data") };
+        let mut record = HandlerRecord::load_from(&xdg.root.join("state/url-handler")).unwrap();
+        let handler = register(&xdg.dirs, &root, &mut record).unwrap();
+
+        xdg.write("config/mimeapps.list", &default_list("other.desktop"));
+        fs::write(&handler, "edited").unwrap();
+        assert_eq!(unregister(&mut record).unwrap(), [format!("Kept {}, since it was changed", handler.display())]);
+        assert_eq!(fs::read_to_string(xdg.dirs.config_home.join("mimeapps.list")).unwrap(), default_list("other.desktop"));
+        assert!(handler.exists() && record.is_empty());
+    }
+
+    #[test]
+    fn round_trips_records() {
+        let xdg = TempXdg::new("record");
+        let path = xdg.root.join("state/url-handler");
+        let list = xdg.root.join("config/with\ttab/mimeapps.list");
+
+        for value in [Some(""), Some("\ta.desktop;\\"), None] {
+            let record = HandlerRecord {
+                path: path.clone(),
+                handler: Some((xdg.root.join("handler.desktop"), "abc".to_string())),
+                replaced: Some((list.clone(), value.map(String::from))),
+            };
+            record.save().unwrap();
+            assert_eq!(HandlerRecord::load_from(&path).unwrap(), record);
+        }
+
+        // Nothing left to undo removes the file
+        HandlerRecord { path: path.clone(), ..Default::default() }.save().unwrap();
+        assert!(!path.exists());
+        assert!(HandlerRecord::load_from(&path).unwrap().is_empty());
+
+        fs::write(&path, "default\ta.desktop;\n").unwrap();
+        assert!(matches!(HandlerRecord::load_from(&path).unwrap_err().kind, StateErrorKind::Invalid { line: 1 }));
+    }
+}
@@ -0,0 +1,315 @@
+//! A parser for Valve's text KeyValues format, used by files such as `libraryfolders.vdf` and `appmanifest_<id>.acf`.
+
+use std::fmt;
+
+/// A value in a KeyValues document.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VdfValue {
+    /// A plain string.
+    String(String),
+    /// A nested block of key-value pairs.
+    Object(VdfObject),
+}
+
+impl VdfValue {
+    /// Returns the string if this value is one.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            VdfValue::String(s) => Some(s),
+            VdfValue::Object(_) => None
+        }
+    }
+
+    /// Returns the block if this value is one.
+    pub fn as_object(&self) -> Option<&VdfObject> {
+        match self {
+            VdfValue::String(_) => None,
+            VdfValue::Object(o) => Some(o)
+        }
+    }
+}
+
+/// An ordered block of key-value pairs.
+///
+/// Keys are kept in file order and may repeat. Lookups are case-insensitive, like Steam's own.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VdfObject {
+    entries: Vec<(String, VdfValue)>,
+}
+
+impl VdfObject {
+    /// Returns the first value stored under `key`.
+    pub fn get(&self, key: &str) -> Option<&VdfValue> {
+        self.entries.iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, v)| v)
+    }
+
+    /// Returns the first string stored under `key`.
+    pub fn get_str(&self, key: &str) -> Option<&str> {
+        self.get(key).and_then(VdfValue::as_str)
+    }
+
+    /// Returns the first block stored under `key`.
+    pub fn get_object(&self, key: &str) -> Option<&VdfObject> {
+        self.get(key).and_then(VdfValue::as_object)
+    }
+
+    /// Iterates over every key-value pair in file order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &VdfValue)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+
+    /// Appends a key-value pair.
+    pub fn push(&mut self, key: String, value: VdfValue) {
+        self.entries.push((key, value));
+    }
+
+    /// Returns `true` if the block has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Possible errors when parsing a KeyValues document.
+#[derive(Debug, Clone)]
+pub struct VdfParseError {
+    /// The specific kind of error.
+    pub kind: VdfParseErrorKind,
+    /// The line the error occured on, starting at 1.
+    pub line: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum VdfParseErrorKind {
+    /// The document ended inside a string or block.
+    UnexpectedEof,
+    /// A `{` appeared where a key was expected, or a key was not followed by a value.
+    UnexpectedToken,
+    /// A `}` appeared without a matching `{`.
+    UnbalancedBrace,
+}
+
+impl fmt::Display for VdfParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let msg = match self.kind {
+            VdfParseErrorKind::UnexpectedEof => "unexpected end of file",
+            VdfParseErrorKind::UnexpectedToken => "unexpected token",
+            VdfParseErrorKind::UnbalancedBrace => "unbalanced closing brace",
+        };
+
+        write!(f, "{} on line {}", msg, self.line)
+    }
+}
+
+enum Token {
+    Str(String),
+    Open,
+    Close,
+}
+
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Lexer<'a> {
+    fn error(&self, kind: VdfParseErrorKind) -> VdfParseError {
+        VdfParseError { kind, line: self.line }
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, VdfParseError> {
+        loop {
+            let c = match self.chars.next() {
+                Some(c) => c,
+                None => return Ok(None)
+            };
+
+            match c {
+                '\n' => self.line += 1,
+                c if c.is_whitespace() => {},
+                '/' if self.chars.peek() == Some(&'/') => {
+                    // Comments run to the end of the line
+                    for c in self.chars.by_ref() {
+                        if c == '\n' {
+                            self.line += 1;
+                            break;
+                        }
+                    }
+                },
+                '[' => {
+                    // Platform conditionals such as `[$WIN32]` are not evaluated
+                    for c in self.chars.by_ref() {
+                        if c == ']' {
+                            break;
+                        }
+                    }
+                },
+                '{' => return Ok(Some(Token::Open)),
+                '}' => return Ok(Some(Token::Close)),
+                '"' => return self.quoted().map(|s| Some(Token::Str(s))),
+                c => {
+                    let mut s = String::from(c);
+                    while let Some(&c) = self.chars.peek() {
+                        if c.is_whitespace() || c == '"' || c == '{' || c == '}' {
+                            break;
+                        }
+                        s.push(c);
+                        self.chars.next();
+                    }
+                    return Ok(Some(Token::Str(s)));
+                }
+            }
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, VdfParseError> {
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                None => return Err(self.error(VdfParseErrorKind::UnexpectedEof)),
+                Some('"') => return Ok(s),
+                Some('\\') => match self.chars.next() {
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    Some('\\') => s.push('\\'),
+                    Some('"') => s.push('"'),
+                    Some(c) => {
+                        // Unknown escapes are kept verbatim, which matters for Windows paths
+                        s.push('\\');
+                        s.push(c);
+                    },
+                    None => return Err(self.error(VdfParseErrorKind::UnexpectedEof))
+                },
+                Some(c) => {
+                    if c == '\n' {
+                        self.line += 1;
+                    }
+                    s.push(c);
+                }
+            }
+        }
+    }
+}
+
+/// Parses a text KeyValues document into its top-level block.
+///
+/// Files such as `appmanifest_<id>.acf` contain a single named root block, which will be the only entry of the result.
+pub fn parse(input: &str) -> Result<VdfObject, VdfParseError> {
+    let mut lexer = Lexer { chars: input.chars().peekable(), line: 1 };
+    let mut stack = vec![VdfObject::default()];
+    let mut keys: Vec<String> = Vec::new();
+
+    loop {
+        let key = match lexer.next_token()? {
+            None => break,
+            Some(Token::Str(s)) => s,
+            Some(Token::Close) => {
+                if stack.len() == 1 {
+                    return Err(lexer.error(VdfParseErrorKind::UnbalancedBrace));
+                }
+                let object = stack.pop().unwrap();
+                let key = keys.pop().unwrap();
+                stack.last_mut().unwrap().push(key, VdfValue::Object(object));
+                continue;
+            },
+            Some(Token::Open) => return Err(lexer.error(VdfParseErrorKind::UnexpectedToken))
+        };
+
+        match lexer.next_token()? {
+            Some(Token::Str(value)) => stack.last_mut().unwrap().push(key, VdfValue::String(value)),
+            Some(Token::Open) => {
+                keys.push(key);
+                stack.push(VdfObject::default());
+            },
+            Some(Token::Close) => return Err(lexer.error(VdfParseErrorKind::UnexpectedToken)),
+            None => return Err(lexer.error(VdfParseErrorKind::UnexpectedEof))
+        }
+    }
+
+    if stack.len() != 1 {
+        return Err(lexer.error(VdfParseErrorKind::UnexpectedEof));
+    }
+
+    Ok(stack.pop().unwrap())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY_FOLDERS: &str = r#""libraryfolders"
+{
+	"0"
+	{
+		"path"		"/home/user/.local/share/Steam"
+		"label"		""
+		"apps"
+		{
+			"228980"		"167892372"
+			"1091500"		"70384467113"
+		}
+	}
+	"1"
+	{
+		"path"		"/mnt/games\\SteamLibrary"
+	}
+}
+"#;
+
+    const MANIFEST: &str = r#""AppState"
+{
+	"appid"		"1091500"
+	"name"		"Cyberpunk 2077"
+	"StateFlags"		"4"
+	// Written by Steam
+	"UserConfig"
+	{
+		"language"		"english"
+	}
+}
+"#;
+
+    #[test]
+    fn parses_library_folders() {
+        let root = parse(LIBRARY_FOLDERS).unwrap();
+        let folders = root.get_object("libraryfolders").unwrap();
+        let first = folders.get_object("0").unwrap();
+
+        assert_eq!(first.get_str("path"), Some("/home/user/.local/share/Steam"));
+        assert_eq!(first.get_str("label"), Some(""));
+        let apps: Vec<&str> = first.get_object("apps").unwrap().iter().map(|(k, _)| k).collect();
+        assert_eq!(apps, ["228980", "1091500"]);
+        assert_eq!(folders.get_object("1").and_then(|f| f.get_str("path")), Some("/mnt/games\\SteamLibrary"));
+    }
+
+    #[test]
+    fn parses_manifests_with_comments_case_insensitively() {
+        let root = parse(MANIFEST).unwrap();
+        let state = root.get_object("appstate").unwrap();
+
+        assert_eq!(state.get_str("AppID"), Some("1091500"));
+        assert_eq!(state.get_str("stateflags"), Some("4"));
+        assert_eq!(state.get_object("UserConfig").and_then(|c| c.get_str("language")), Some("english"));
+    }
+
+    #[test]
+    fn parses_unquoted_tokens_escapes_and_conditionals() {
+        let root = parse("key value\n\"esc\" \"a\\\"b\\nc\"\n\"cond\" \"1\" [$WIN32]\n").unwrap();
+
+        assert_eq!(root.get_str("key"), Some("value"));
+        assert_eq!(root.get_str("esc"), Some("a\"b\nc"));
+        assert_eq!(root.get_str("cond"), Some("1"));
+    }
+
+    #[test]
+    fn reports_errors_with_their_line() {
+        let unbalanced = parse("\"a\" \"b\"\n}").unwrap_err();
+        assert!(matches!(unbalanced.kind, VdfParseErrorKind::UnbalancedBrace));
+        assert_eq!(unbalanced.line, 2);
+
+        assert!(matches!(parse("\"a\"\n{\n\"b\" \"c\"\n").unwrap_err().kind, VdfParseErrorKind::UnexpectedEof));
+        assert!(matches!(parse("\"a\" }").unwrap_err().kind, VdfParseErrorKind::UnexpectedToken));
+        assert!(matches!(parse("\"a\" \"unterminated").unwrap_err().kind, VdfParseErrorKind::UnexpectedEof));
+    }
+}
@@ -0,0 +1,240 @@
+//! Window classes of games, which docks use to tie windows to their launchers.
+//!
+//! Steam and Proton games use `steam_app_<id>`, but native games pick their own.
+//! Those are learned by watching which windows running games open, and kept at `$XDG_STATE_HOME/steam-shortcut-sync/wm-classes`.
+
+use std::{collections::{BTreeMap, HashSet}, fmt::Write as _, fs, io, path::{Path, PathBuf}, process::Command};
+
+use crate::state::{self, StateError, StateErrorKind};
+
+/// The class Steam and Proton games give their windows.
+pub fn default_class(id: &str) -> String {
+    format!("steam_app_{}", id)
+}
+
+/// Window classes that differ from `default_class`, by app id.
+#[derive(Debug, Clone, Default)]
+pub struct LearnedClasses {
+    path: PathBuf,
+    classes: BTreeMap<String, String>,
+}
+
+impl LearnedClasses {
+    /// Loads the classes from their default location, or starts with none if there are none yet.
+    pub fn load() -> Result<LearnedClasses, StateError> {
+        let path = state::state_dir()
+            .map_err(|_| StateError { kind: StateErrorKind::NoStateDir })?
+            .join("wm-classes");
+
+        Self::load_from(&path)
+    }
+
+    /// Loads the classes from their default location, starting over with none if they can't be read.
+    ///
+    /// A corrupt file is replaced the next time something is learned.
+    pub fn load_or_empty() -> LearnedClasses {
+        let path = state::state_dir().map(|d| d.join("wm-classes")).unwrap_or_default();
+        match Self::load_from(&path) {
+            Ok(learned) => learned,
+            Err(e) => {
+                eprintln!("Unable to load learned window classes: {:?}", e.kind);
+                LearnedClasses { path, classes: BTreeMap::new() }
+            }
+        }
+    }
+
+    /// Loads the classes stored at `path`, or starts with none if it doesn't exist.
+    pub fn load_from(path: &Path) -> Result<LearnedClasses, StateError> {
+        let mut learned = LearnedClasses { path: path.to_path_buf(), classes: BTreeMap::new() };
+
+        let contents = match fs::read_to_string(path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(learned),
+            Err(e) => return Err(StateError { kind: StateErrorKind::Io(e) })
+        };
+
+        for (i, line) in contents.lines().enumerate().filter(|(_, l)| !l.starts_with('#') && !l.is_empty()) {
+            match line.split_once('\t') {
+                Some((id, class)) => learned.classes.insert(id.to_string(), class.to_string()),
+                None => return Err(StateError { kind: StateErrorKind::Invalid { line: i + 1 } })
+            };
+        }
+
+        Ok(learned)
+    }
+
+    /// Writes the classes back to where they were loaded from, replacing the old file atomically.
+    pub fn save(&self) -> io::Result<()> {
+        let mut contents = String::from("# Window classes learned by Steam Shortcut Sync, one app id and class per line\n");
+        for (id, class) in &self.classes {
+            let _ = writeln!(contents, "{}\t{}", id, class);
+        }
+
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let tmp = self.path.with_extension("tmp");
+        fs::write(&tmp, contents)?;
+        fs::rename(&tmp, &self.path)
+    }
+
+    /// The class learned for `id`, if there is one.
+    pub fn get(&self, id: &str) -> Option<&str> {
+        self.classes.get(id).map(String::as_str)
+    }
+
+    /// Records that a window of `id` has `class`, returning `true` if that is news.
+    ///
+    /// The class seen last is kept, since games often open a launcher window before the game itself.
+    /// Seeing the default class again forgets the learned one.
+    pub fn learn(&mut self, id: &str, class: &str) -> bool {
+        if class == default_class(id) {
+            return self.classes.remove(id).is_some();
+        }
+        if self.get(id) == Some(class) {
+            return false;
+        }
+
+        self.classes.insert(id.to_string(), class.to_string());
+        true
+    }
+}
+
+/// Finds the windows games opened since the last call, returning the app id and class of each one.
+///
+/// Windows are listed with `xprop`, so only X11 and XWayland windows are seen. A window belongs to a game if its process was started by Steam.
+/// Windows of sandboxed processes, such as games of the Flatpak Steam, report a process id from another PID namespace, so they are only tied to a game when that id is unambiguous.
+/// `seen` holds the windows that were already looked at, and is kept up to date.
+pub fn observe_windows(seen: &mut HashSet<String>) -> io::Result<Vec<(String, String)>> {
+    let clients = xprop(&["-root", "_NET_CLIENT_LIST"])?;
+    let windows: Vec<String> = clients.split_once('#')
+        .map(|(_, ids)| ids.split(',').map(|w| w.trim().to_string()).filter(|w| !w.is_empty()).collect())
+        .unwrap_or_default();
+    seen.retain(|w| windows.contains(w));
+
+    let mut observed = Vec::new();
+    for window in windows {
+        if seen.contains(&window) {
+            continue;
+        }
+
+        let properties = match xprop(&["-id", &window, "WM_CLASS", "_NET_WM_PID"]) {
+            Ok(p) => p,
+            // The window may have closed in the meantime
+            Err(_) => continue
+        };
+
+        // Windows get their class shortly after they appear, so they are looked at again until they have one
+        let class = match properties.lines().find(|l| l.starts_with("WM_CLASS")).and_then(parse_class) {
+            Some(class) => class,
+            None => continue
+        };
+        seen.insert(window);
+
+        let pid = properties.lines()
+            .find(|l| l.starts_with("_NET_WM_PID"))
+            .and_then(|l| l.split_once('='))
+            .and_then(|(_, pid)| pid.trim().parse::<u32>().ok());
+        if let Some(id) = pid.and_then(steam_game_id) {
+            observed.push((id, class));
+        }
+    }
+
+    Ok(observed)
+}
+
+fn xprop(args: &[&str]) -> io::Result<String> {
+    let output = Command::new("xprop").args(args).output()?;
+    if !output.status.success() {
+        return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// `WM_CLASS(STRING) = "instance", "Class"`, where docks match the class
+fn parse_class(line: &str) -> Option<String> {
+    let (_, values) = line.split_once('=')?;
+    let class = values.rsplit(',').next()?.trim().trim_matches('"');
+
+    Some(class.to_string()).filter(|c| !c.is_empty())
+}
+
+// `_NET_WM_PID` is the process id in the client's own PID namespace, which isn't ours for sandboxed clients.
+// Every process with that id in its innermost namespace is a candidate, and the game id is only trusted if the candidates that have one agree on it.
+fn steam_game_id(pid: u32) -> Option<String> {
+    let mut ids = fs::read_dir("/proc").ok()?
+        .filter_map(|e| e.ok())
+        .filter_map(|e| e.file_name().to_str()?.parse::<u32>().ok())
+        .filter(|&p| innermost_pid(p) == Some(pid))
+        .filter_map(environ_game_id);
+
+    let id = ids.next()?;
+    ids.all(|other| other == id).then_some(id)
+}
+
+// Kernels before 4.1 don't list `NSpid`, and don't get the sandbox lookup either
+fn innermost_pid(pid: u32) -> Option<u32> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+
+    match status.lines().find_map(|l| l.strip_prefix("NSpid:")) {
+        Some(pids) => pids.split_whitespace().last()?.parse().ok(),
+        None => Some(pid)
+    }
+}
+
+// Steam sets the game id for everything it launches, which for non-Steam shortcuts is the 64-bit id their launchers use
+fn environ_game_id(pid: u32) -> Option<String> {
+    let environ = fs::read(format!("/proc/{}/environ", pid)).ok()?;
+    let vars: Vec<&[u8]> = environ.split(|&b| b == 0).collect();
+
+    ["SteamGameId=", "SteamAppId="].iter().find_map(|key| {
+        let value = vars.iter().find_map(|v| v.strip_prefix(key.as_bytes()))?;
+        let value = String::from_utf8_lossy(value).into_owned();
+        Some(value).filter(|v| v != "0" && !v.is_empty() && v.bytes().all(|b| b.is_ascii_digit()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    #[test]
+    fn learns_the_latest_unusual_class() {
+        let path = env::temp_dir().join(format!("steam-shortcut-sync-wm-classes-{}", process::id()));
+        let mut learned = LearnedClasses::load_from(&path).unwrap();
+
+        assert!(!learned.learn("10", "steam_app_10"));
+        // The launcher opens before the game
+        assert!(learned.learn("20", "launcher"));
+        assert!(learned.learn("20", "doometernal"));
+        assert!(!learned.learn("20", "doometernal"));
+        assert!(learned.learn("30", "other"));
+        assert!(learned.learn("30", "steam_app_30"));
+        learned.save().unwrap();
+
+        let loaded = LearnedClasses::load_from(&path).unwrap();
+        assert_eq!(loaded.get("20"), Some("doometernal"));
+        assert_eq!(loaded.get("10"), None);
+        assert_eq!(loaded.get("30"), None);
+
+        fs::write(&path, "20 doometernal\n").unwrap();
+        assert!(matches!(LearnedClasses::load_from(&path).unwrap_err().kind, StateErrorKind::Invalid { line: 1 }));
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn parses_window_classes() {
+        assert_eq!(parse_class(r#"WM_CLASS(STRING) = "doom", "DOOMEternal""#).as_deref(), Some("DOOMEternal"));
+        assert_eq!(parse_class(r#"WM_CLASS(STRING) = """#), None);
+    }
+
+    #[test]
+    fn finds_own_process_by_its_pid() {
+        assert_eq!(innermost_pid(process::id()), Some(process::id()));
+    }
+}